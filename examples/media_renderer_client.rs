@@ -24,7 +24,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let kodi_device = kodi_device.unwrap();
     let device_client = DeviceClient::new(&kodi_device.location)?.connect().await?;
-    let mut media_renderer = MediaRendererClient::new(device_client);
+    let media_renderer = MediaRendererClient::new(device_client);
 
     let options = LoadOptions {
         dlna_features: Some(
@@ -38,15 +38,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }),
         autoplay: true,
         object_class: Some(ObjectClass::Video),
-        ..Default::default()
     };
 
     let media_url =
         "http://commondatastorage.googleapis.com/gtv-videos-bucket/sample/BigBuckBunny.mp4";
 
     media_renderer.load(media_url, options).await?;
-    let events = media_renderer.subscribe().await;
-    tokio::pin!(events);
+    let mut events = media_renderer.subscribe_all().await?;
 
     while let Some(event) = events.next().await {
         println!("\n{}\n", event);
@@ -1,51 +1,83 @@
-use std::{collections::HashMap, env, net::TcpListener, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    net::{Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
 
 use crate::{
-    parser::{
-        deserialize_metadata, parse_av_transport_uri_metadata, parse_current_play_mode,
-        parse_current_track_metadata, parse_last_change, parse_location, parse_transport_state,
+    eventing::{
+        local_address_to, send_subscribe, send_unsubscribe, Callback, Listener, Registry,
+        Subscription,
     },
-    types::{AVTransportEvent, Device, Event, Service},
-    BROADCAST_EVENT,
-};
-use anyhow::{anyhow, Result};
-use hyper::{
-    server::conn::AddrStream,
-    service::{make_service_fn, service_fn},
+    parser::{parse_location, parse_query_state_variable, parse_upnp_error},
+    recording::{Recorder, RecordingTransport},
+    transport::{HttpRequest, HttpTransport, HyperTransport},
+    types::{CallOptions, ClientConfig, Device, Service, StateVariable},
+    Error, Result,
 };
-use hyper::{Body, Request, Response, Server};
-use surf::{Client, Config, Url};
-use tokio::sync::Mutex;
+use url::Url;
 use xml_builder::{XMLBuilder, XMLElement, XMLVersion};
 
+const CONTROL_NAMESPACE: &str = "urn:schemas-upnp-org:control-1-0";
+
 #[derive(Clone)]
 pub struct DeviceClient {
     base_url: Url,
-    http_client: Client,
+    transport: Arc<dyn HttpTransport>,
+    custom_transport: Option<Arc<dyn HttpTransport>>,
+    config: ClientConfig,
     device: Option<Device>,
-    stop: Arc<Mutex<bool>>,
+    recorder: Option<Recorder>,
 }
 
 impl DeviceClient {
     pub fn new(url: &str) -> Result<Self> {
         Ok(Self {
             base_url: Url::parse(url)?,
-            http_client: Config::new()
-                .set_timeout(Some(Duration::from_secs(5)))
-                .try_into()?,
+            transport: Arc::new(HyperTransport::default()),
+            custom_transport: None,
+            config: ClientConfig::default(),
             device: None,
-            stop: Arc::new(Mutex::new(false)),
+            recorder: None,
         })
     }
 
+    /// Use the given timeouts and retry policy. The timeouts apply to the default
+    /// transport, a transport from `with_transport` is kept as is.
+    pub fn with_config(mut self, config: ClientConfig) -> Self {
+        self.config = config;
+        self.build_transport();
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.custom_transport = Some(transport);
+        self.build_transport();
+        self
+    }
+
+    /// Record description fetches, SOAP calls and NOTIFY bodies into `recorder`.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self.build_transport();
+        self
+    }
+
+    // Builders may come in any order, so the transport is put together from scratch
+    fn build_transport(&mut self) {
+        let transport = match &self.custom_transport {
+            Some(transport) => transport.clone(),
+            None => Arc::new(HyperTransport::from(&self.config)),
+        };
+        self.transport = match &self.recorder {
+            Some(recorder) => Arc::new(RecordingTransport::new(transport, recorder.clone())),
+            None => transport,
+        };
+    }
+
     pub async fn connect(&mut self) -> Result<Self> {
-        self.device = Some(parse_location(self.base_url.as_str()).await?);
-        Ok(Self {
-            base_url: self.base_url.clone(),
-            http_client: self.http_client.clone(),
-            device: self.device.clone(),
-            stop: self.stop.clone(),
-        })
+        self.device = Some(parse_location(self.base_url.as_str(), self.transport.as_ref()).await?);
+        Ok(self.clone())
     }
 
     pub fn ip(&self) -> String {
@@ -57,9 +89,20 @@ impl DeviceClient {
         service_id: &str,
         action_name: &str,
         params: HashMap<String, String>,
+    ) -> Result<String> {
+        self.call_action_with_options(service_id, action_name, params, CallOptions::default())
+            .await
+    }
+
+    pub async fn call_action_with_options(
+        &self,
+        service_id: &str,
+        action_name: &str,
+        params: HashMap<String, String>,
+        options: CallOptions,
     ) -> Result<String> {
         if self.device.is_none() {
-            return Err(anyhow!("Device not connected"));
+            return Err(Error::DeviceNotConnected);
         }
         let service_id = resolve_service(service_id);
         let service = self.get_service_description(&service_id).await?;
@@ -68,11 +111,68 @@ impl DeviceClient {
         let action = service.actions.iter().find(|a| a.name == action_name);
         match action {
             Some(_) => {
-                self.call_action_internal(&service, action_name, params)
+                self.call_action_internal(&service, action_name, params, options)
                     .await
             }
-            None => Err(anyhow!("Action not found")),
+            None => Err(Error::ActionNotFound(action_name.to_string())),
+        }
+    }
+
+    pub async fn query_state_variable(
+        &self,
+        service_id: &str,
+        var_name: &str,
+    ) -> Result<Option<String>> {
+        if self.device.is_none() {
+            return Err(Error::DeviceNotConnected);
         }
+        let service_id = resolve_service(service_id);
+        let service = self.get_service_description(&service_id).await?;
+
+        let mut params = HashMap::new();
+        params.insert("u:varName".to_string(), var_name.to_string());
+        let xml = build_soap_request(CONTROL_NAMESPACE, "QueryStateVariable", params)?;
+        let soap_action = format!("\"{}#QueryStateVariable\"", CONTROL_NAMESPACE);
+
+        let (status, body) = self
+            .send_soap_request(
+                &service.control_url,
+                soap_action,
+                xml,
+                true,
+                CallOptions::default(),
+            )
+            .await?;
+
+        // QueryStateVariable is deprecated since UPnP 1.1, devices that dropped it
+        // answer with 401 (Invalid Action), either as HTTP status or as UPnPError
+        if status == 401 {
+            return Ok(None);
+        }
+        if let Some((code, description)) = parse_upnp_error(&body)? {
+            return match code {
+                401 => Ok(None),
+                _ => Err(Error::SoapFault { code, description }),
+            };
+        }
+        if !(200..300).contains(&status) {
+            return Err(Error::HttpStatus { status, body });
+        }
+        parse_query_state_variable(&body)
+    }
+
+    /// State variable `name` as advertised in the service description.
+    pub async fn state_variable(
+        &self,
+        service_id: &str,
+        name: &str,
+    ) -> Result<Option<StateVariable>> {
+        let service_id = resolve_service(service_id);
+        let service = self.get_service_description(&service_id).await?;
+        Ok(service
+            .state_variables
+            .into_iter()
+            .find(|variable| variable.name == name))
     }
 
     async fn call_action_internal(
@@ -80,55 +180,64 @@ impl DeviceClient {
         service: &Service,
         action_name: &str,
         params: HashMap<String, String>,
+        options: CallOptions,
     ) -> Result<String> {
-        let control_url = Url::parse(&service.control_url)?;
-
-        let mut xml = XMLBuilder::new()
-            .version(XMLVersion::XML1_1)
-            .encoding("UTF-8".into())
-            .build();
-
-        let mut envelope = XMLElement::new("s:Envelope");
-        envelope.add_attribute("xmlns:s", "http://schemas.xmlsoap.org/soap/envelope/");
-        envelope.add_attribute(
-            "s:encodingStyle",
-            "http://schemas.xmlsoap.org/soap/encoding/",
-        );
-
-        let mut body = XMLElement::new("s:Body");
-        let action = format!("u:{}", action_name);
-        let mut action = XMLElement::new(action.as_str());
-        action.add_attribute("xmlns:u", service.service_type.as_str());
-
-        for (name, value) in params {
-            let mut param = XMLElement::new(name.as_str());
-            param.add_text(value).map_err(|e| anyhow!("{:?}", e))?;
-            action.add_child(param).map_err(|e| anyhow!("{:?}", e))?;
-        }
-
-        body.add_child(action).map_err(|e| anyhow!("{:?}", e))?;
-        envelope.add_child(body).map_err(|e| anyhow!("{:?}", e))?;
-
-        xml.set_root_element(envelope);
-
-        let mut writer: Vec<u8> = Vec::new();
-        xml.generate(&mut writer).map_err(|e| anyhow!("{:?}", e))?;
-        let xml = String::from_utf8(writer)?;
-
+        let xml = build_soap_request(&service.service_type, action_name, params)?;
         let soap_action = format!("\"{}#{}\"", service.service_type, action_name);
+        let (status, body) = self
+            .send_soap_request(
+                &service.control_url,
+                soap_action,
+                xml,
+                is_idempotent(action_name),
+                options,
+            )
+            .await?;
+        if let Some((code, description)) = parse_upnp_error(&body)? {
+            return Err(Error::SoapFault { code, description });
+        }
+        if !(200..300).contains(&status) {
+            return Err(Error::HttpStatus { status, body });
+        }
+        Ok(body)
+    }
 
-        let mut res = self
-            .http_client
-            .post(control_url)
+    async fn send_soap_request(
+        &self,
+        control_url: &str,
+        soap_action: String,
+        xml: String,
+        idempotent: bool,
+        options: CallOptions,
+    ) -> Result<(u16, String)> {
+        let req = HttpRequest::new("POST", control_url)
             .header("Content-Type", "text/xml; charset=\"utf-8\"")
-            .header("Content-Length", xml.len().to_string())
-            .header("SOAPACTION", soap_action)
+            .header("Content-Length", &xml.len().to_string())
+            .header("SOAPACTION", &soap_action)
             .header("Connection", "close")
-            .body_string(xml.clone())
-            .send()
-            .await
-            .map_err(|e| anyhow!(e.to_string()))?;
-        res.body_string().await.map_err(|e| anyhow!(e.to_string()))
+            .body(xml)
+            .timeout(options.timeout.unwrap_or(self.config.read_timeout));
+
+        let retries = match (options.retries, idempotent) {
+            (Some(retries), _) => retries,
+            (None, true) => self.config.retries,
+            (None, false) => 0,
+        };
+        let mut backoff = self.config.backoff;
+        let mut attempt = 0;
+        loop {
+            let result = self.transport.send(req.clone()).await;
+            match result {
+                Ok(res) if res.status != 503 || attempt >= retries => {
+                    return Ok((res.status, res.body))
+                }
+                Err(e) if attempt >= retries => return Err(e),
+                _ => {}
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.config.max_backoff);
+            attempt += 1;
+        }
     }
 
     async fn get_service_description(&self, service_id: &str) -> Result<Service> {
@@ -137,172 +246,123 @@ impl DeviceClient {
                 .services
                 .iter()
                 .find(|s| s.service_id == service_id)
-                .ok_or_else(|| {
-                    anyhow!(
-                        "Service with requested service_id {} does not exist",
-                        service_id
-                    )
-                })?;
+                .ok_or_else(|| Error::ServiceNotFound(service_id.to_string()))?;
             return Ok(service.clone());
         }
-        Err(anyhow!("Device not connected"))
+        Err(Error::DeviceNotConnected)
     }
 
-    pub async fn subscribe(&mut self, service_id: &str) -> Result<()> {
+    pub async fn subscribe(&self, service_id: &str) -> Result<Subscription> {
         if self.device.is_none() {
-            return Err(anyhow!("Device not connected"));
+            return Err(Error::DeviceNotConnected);
         }
         let service_id = resolve_service(service_id);
         let service = self.get_service_description(&service_id).await?;
 
-        let user_agent = format!(
-            "upnp-client/{} ({})",
-            env!("CARGO_PKG_VERSION"),
-            env::consts::OS
-        );
-
-        let (address, port) = self.ensure_eventing_server().await?;
-        let callback = format!("<http://{}:{}>", address, port);
-
-        let client = hyper::Client::new();
-        let req = hyper::Request::builder()
-            .method("SUBSCRIBE")
-            .uri(service.event_sub_url.clone())
-            .header("CALLBACK", callback)
-            .header("NT", "upnp:event")
-            .header("TIMEOUT", "Second-1800")
-            .header("USER-AGENT", user_agent)
-            .body(hyper::Body::empty())?;
-        client.request(req).await?;
-        Ok(())
+        let listener = Listener::acquire(self.config.event_port).await?;
+        let path = listener.callback_path();
+        let callback = Callback {
+            url: format!("<http://{}{}>", self.callback_host(&listener)?, path),
+            path,
+        };
+
+        let _in_flight = Registry::begin_subscribe(listener.registry());
+        let (sid, timeout) = send_subscribe(
+            self.transport.as_ref(),
+            &service.event_sub_url,
+            &callback.url,
+        )
+        .await?;
+
+        Ok(Subscription::new(
+            sid,
+            timeout,
+            &service,
+            callback,
+            self.transport.clone(),
+            listener,
+            self.recorder.clone(),
+        ))
+    }
+
+    /// `host:port` devices reach the event listener on.
+    fn callback_host(&self, listener: &Listener) -> Result<String> {
+        let port = listener.port();
+        if let Some(host) = &self.config.callback_host {
+            if host.parse::<Ipv6Addr>().is_ok() {
+                return Ok(format!("[{}]:{}", host, port));
+            }
+            return Ok(format!("{}:{}", host, port));
+        }
+        let device = self
+            .base_url
+            .socket_addrs(|| None)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Eventing(format!("Cannot resolve {}", self.base_url)))?;
+        let ip = local_address_to(device)?;
+        if ip.is_ipv6() && !listener.accepts_ipv6() {
+            return Err(Error::Eventing(format!(
+                "Cannot receive events from {} over IPv6, set ClientConfig::callback_host",
+                self.base_url
+            )));
+        }
+        Ok(SocketAddr::new(ip, port).to_string())
     }
 
-    pub async fn unsubscribe(&mut self, service_id: &str, sid: &str) -> Result<()> {
+    pub async fn unsubscribe(&self, service_id: &str, sid: &str) -> Result<()> {
         if self.device.is_none() {
-            return Err(anyhow!("Device not connected"));
+            return Err(Error::DeviceNotConnected);
         }
         let service_id = resolve_service(service_id);
         let service = self.get_service_description(&service_id).await?;
-        let client = hyper::Client::new();
-        let req = hyper::Request::builder()
-            .method("UNSUBSCRIBE")
-            .uri(service.event_sub_url.clone())
-            .header("SID", sid)
-            .body(hyper::Body::empty())?;
-
-        client.request(req).await?;
-
-        self.release_eventing_server().await?;
-        Ok(())
+        send_unsubscribe(self.transport.as_ref(), &service.event_sub_url, sid).await
     }
+}
 
-    async fn ensure_eventing_server(&mut self) -> Result<(String, u16)> {
-        let addr: &str = "0.0.0.0:0";
-        let listener = TcpListener::bind(addr)?;
-
-        let service = make_service_fn(|_: &AddrStream| async {
-            Ok::<_, hyper::Error>(service_fn(|req: Request<Body>| async move {
-                let sid = req
-                    .headers()
-                    .get("sid")
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string();
-                let body = hyper::body::to_bytes(req.into_body()).await?;
-                let xml = String::from_utf8(body.to_vec()).unwrap();
-
-                let last_change = parse_last_change(xml.as_str()).unwrap();
-                let last_change = last_change.unwrap_or_default();
-
-                let transport_state = parse_transport_state(last_change.as_str()).unwrap();
-                let play_mode = parse_current_play_mode(last_change.as_str()).unwrap();
-                let av_transport_uri_metadata =
-                    parse_av_transport_uri_metadata(last_change.as_str()).unwrap();
-                let current_track_metadata =
-                    parse_current_track_metadata(last_change.as_str()).unwrap();
-
-                if let Some(state) = transport_state {
-                    let tx = BROADCAST_EVENT.lock().unwrap();
-                    let tx = tx.as_ref();
-                    let ev = AVTransportEvent::TransportState {
-                        sid: sid.clone(),
-                        transport_state: state,
-                    };
-                    tx.unwrap().send(Event::AVTransport(ev)).unwrap();
-                }
-
-                if let Some(mode) = play_mode {
-                    let tx = BROADCAST_EVENT.lock().unwrap();
-                    let tx = tx.as_ref();
-                    let ev = AVTransportEvent::CurrentPlayMode {
-                        sid: sid.clone(),
-                        play_mode: mode,
-                    };
-                    tx.unwrap().send(Event::AVTransport(ev)).unwrap();
-                }
-
-                if let Some(metadata) = av_transport_uri_metadata {
-                    let tx = BROADCAST_EVENT.lock().unwrap();
-                    let tx = tx.as_ref();
-                    let m = deserialize_metadata(metadata.as_str()).unwrap();
-                    let ev = AVTransportEvent::AVTransportURIMetaData {
-                        sid: sid.clone(),
-                        url: m.url,
-                        title: m.title,
-                        artist: m.artist,
-                        album: m.album,
-                        album_art_uri: m.album_art_uri,
-                        genre: m.genre,
-                    };
-                    tx.unwrap().send(Event::AVTransport(ev)).unwrap();
-                }
-
-                if let Some(metadata) = current_track_metadata {
-                    let m = deserialize_metadata(metadata.as_str()).unwrap();
-                    let tx = BROADCAST_EVENT.lock().unwrap();
-                    let tx = tx.as_ref();
-                    let ev = AVTransportEvent::CurrentTrackMetadata {
-                        sid: sid.clone(),
-                        url: m.url,
-                        title: m.title,
-                        artist: m.artist,
-                        album: m.album,
-                        album_art_uri: m.album_art_uri,
-                        genre: m.genre,
-                    };
-                    tx.unwrap().send(Event::AVTransport(ev)).unwrap();
-                }
-
-                Ok::<_, hyper::Error>(Response::new(Body::empty()))
-            }))
-        });
-
-        let server = Server::from_tcp(listener).unwrap().serve(service);
-
-        let address = server.local_addr().ip().to_string();
-        let port = server.local_addr().port();
+fn build_soap_request(
+    namespace: &str,
+    action_name: &str,
+    params: HashMap<String, String>,
+) -> Result<String> {
+    let mut xml = XMLBuilder::new()
+        .version(XMLVersion::XML1_1)
+        .encoding("UTF-8".into())
+        .build();
+
+    let mut envelope = XMLElement::new("s:Envelope");
+    envelope.add_attribute("xmlns:s", "http://schemas.xmlsoap.org/soap/envelope/");
+    envelope.add_attribute(
+        "s:encodingStyle",
+        "http://schemas.xmlsoap.org/soap/encoding/",
+    );
+
+    let mut body = XMLElement::new("s:Body");
+    let action = format!("u:{}", action_name);
+    let mut action = XMLElement::new(action.as_str());
+    action.add_attribute("xmlns:u", namespace);
+
+    for (name, value) in params {
+        let mut param = XMLElement::new(name.as_str());
+        param.add_text(value)?;
+        action.add_child(param)?;
+    }
 
-        let stop = self.stop.clone();
+    body.add_child(action)?;
+    envelope.add_child(body)?;
 
-        tokio::spawn(async move {
-            server.await.unwrap();
-        });
+    xml.set_root_element(envelope);
 
-        tokio::spawn(async move {
-            while !*stop.lock().await {
-                tokio::time::sleep(Duration::from_millis(100)).await;
-            }
-        });
-
-        Ok((address, port))
-    }
+    let mut writer: Vec<u8> = Vec::new();
+    xml.generate(&mut writer)?;
+    Ok(String::from_utf8(writer)?)
+}
 
-    async fn release_eventing_server(&mut self) -> Result<()> {
-        let mut stop = self.stop.lock().await;
-        *stop = true;
-        Ok(())
-    }
+// Actions that only read state can safely be sent again after a timeout
+fn is_idempotent(action_name: &str) -> bool {
+    action_name.starts_with("Get")
+        || action_name.starts_with("List")
+        || matches!(action_name, "Browse" | "Search" | "QueryStateVariable")
 }
 
 fn resolve_service(service_id: &str) -> String {
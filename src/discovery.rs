@@ -1,13 +1,15 @@
-use anyhow::{anyhow, Result};
 use async_stream::stream;
 use futures_util::Stream;
 use std::collections::HashMap;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::str;
+use std::sync::Arc;
 use tokio::net::UdpSocket;
 
 use crate::parser::parse_location;
+use crate::transport::{HttpTransport, HyperTransport};
 use crate::types::Device;
+use crate::{Error, Result};
 
 const DISCOVERY_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
                                  HOST: 239.255.255.250:1900\r\n\
@@ -17,6 +19,14 @@ const DISCOVERY_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
                                  \r\n";
 
 pub async fn discover_pnp_locations() -> Result<impl Stream<Item = Device>> {
+    discover_pnp_locations_with(Arc::new(HyperTransport::default())).await
+}
+
+/// Like `discover_pnp_locations`, fetching device descriptions through `transport`,
+/// e.g. one that speaks https or is shared with the device clients.
+pub async fn discover_pnp_locations_with(
+    transport: Arc<dyn HttpTransport>,
+) -> Result<impl Stream<Item = Device>> {
     let any: SocketAddr = ([0, 0, 0, 0], 0).into();
     let socket = UdpSocket::bind(any).await?;
     socket.join_multicast_v4(Ipv4Addr::new(239, 255, 255, 250), Ipv4Addr::new(0, 0, 0, 0))?;
@@ -37,16 +47,16 @@ pub async fn discover_pnp_locations() -> Result<impl Stream<Item = Device>> {
                 let (size, _) = socket.recv_from(&mut buf).await?;
                 // Convert the response to a string
                 let response =
-                    str::from_utf8(unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, size) })?;
+                    str::from_utf8(unsafe { std::slice::from_raw_parts(buf.as_ptr(), size) })?;
                 let headers = parse_raw_http_response(response)?;
                 let location = headers.get("location")
-                    .ok_or_else(|| anyhow!("Response header missing location"))?
+                    .ok_or_else(|| Error::Discovery("Response header missing location".to_string()))?
                     .to_string();
                 Ok(location)
             }
 
             if let Ok(location) = get_next(&socket).await {
-                if let Ok(device) = parse_location(&location).await {
+                if let Ok(device) = parse_location(&location, transport.as_ref()).await {
                     yield device;
                 }
             }
@@ -68,6 +78,6 @@ fn parse_raw_http_response(response_str: &str) -> Result<HashMap<String, &str>>
             }
             Ok(headers)
         }
-        None => Err(anyhow!("Invalid HTTP response")),
+        None => Err(Error::Discovery("Invalid HTTP response".to_string())),
     }
 }
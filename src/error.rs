@@ -0,0 +1,108 @@
+use std::{
+    num::{ParseFloatError, ParseIntError},
+    str::Utf8Error,
+    string::FromUtf8Error,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Device unreachable: {0}")]
+    Transport(String),
+    #[error("Invalid request: {0}")]
+    Request(String),
+    #[error("Request to {0} timed out")]
+    Timeout(String),
+    #[error("Unexpected HTTP status {status}")]
+    HttpStatus { status: u16, body: String },
+    #[error("SOAP fault {code}: {description}")]
+    SoapFault { code: u32, description: String },
+    #[error("Invalid response from device: {0}")]
+    Parse(String),
+    #[error("Discovery failed: {0}")]
+    Discovery(String),
+    #[error("Eventing failed: {0}")]
+    Eventing(String),
+    #[error("Device not connected")]
+    DeviceNotConnected,
+    #[error("Service with requested service_id {0} does not exist")]
+    ServiceNotFound(String),
+    #[error("Action {0} not found")]
+    ActionNotFound(String),
+    #[error("Action {0} is not allowed by the renderer right now")]
+    ActionNotAllowed(String),
+    #[error("Value {value} is not allowed for {name}")]
+    ValueNotAllowed { name: String, value: String },
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::Transport(e.to_string())
+    }
+}
+
+impl From<http::Error> for Error {
+    fn from(e: http::Error) -> Self {
+        Error::Request(e.to_string())
+    }
+}
+
+impl From<http::method::InvalidMethod> for Error {
+    fn from(e: http::method::InvalidMethod) -> Self {
+        Error::Request(e.to_string())
+    }
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(e: xml::reader::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<elementtree::Error> for Error {
+    fn from(e: elementtree::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<xml_builder::XMLError> for Error {
+    fn from(e: xml_builder::XMLError) -> Self {
+        Error::Parse(format!("{:?}", e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(e: Utf8Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
@@ -0,0 +1,1081 @@
+use std::{
+    collections::HashMap,
+    env, future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+    task::{Context, Poll},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures_util::Stream;
+use hyper::{
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot, watch, Notify,
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    parser::{
+        deserialize_metadata, parse_bool, parse_last_change_values, parse_property_set,
+        parse_subscription_timeout, split_list,
+    },
+    recording::{Exchange, Recorder},
+    transport::{HttpRequest, HttpResponse, HttpTransport},
+    types::{
+        AVTransportEvent, Event, LastChangeValue, PlayMode, RenderingControlEvent, Service,
+        TransportState, TransportStatus,
+    },
+    Error, Result,
+};
+
+const SUBSCRIPTION_TIMEOUT: &str = "Second-1800";
+/// What `SUBSCRIPTION_TIMEOUT` asks for, assumed when the device does not tell.
+const REQUESTED_TIMEOUT: Duration = Duration::from_secs(1800);
+/// How long a listener that is shutting down may hold on to a fixed port.
+const LISTENER_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Renewals are not sent more often than this, whatever timeout the device grants.
+const MIN_RENEWAL_DELAY: Duration = Duration::from_millis(250);
+/// The first delay before a failed renewal is tried again, doubled on each failure
+/// up to `RENEWAL_RETRY_LIMIT`.
+const RENEWAL_RETRY: Duration = Duration::from_secs(1);
+const RENEWAL_RETRY_LIMIT: Duration = Duration::from_secs(60);
+
+/// The `CALLBACK` URL sent to the device and its path on the listener.
+#[derive(Clone)]
+pub(crate) struct Callback {
+    pub url: String,
+    pub path: String,
+}
+
+struct SubscriptionState {
+    sid: String,
+    timeout: Option<Duration>,
+}
+
+/// A GENA subscription and the stream of its events.
+///
+/// The subscription is renewed in the background before it expires. It is cancelled
+/// with `unsubscribe`, or in the background when the handle is dropped.
+pub struct Subscription {
+    state: Arc<Mutex<SubscriptionState>>,
+    path: String,
+    service_id: String,
+    event_sub_url: String,
+    transport: Arc<dyn HttpTransport>,
+    listener: Arc<Listener>,
+    rx: UnboundedReceiver<Event>,
+    renewal: JoinHandle<()>,
+    active: bool,
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        sid: String,
+        timeout: Option<Duration>,
+        service: &Service,
+        callback: Callback,
+        transport: Arc<dyn HttpTransport>,
+        listener: Arc<Listener>,
+        recorder: Option<Recorder>,
+    ) -> Self {
+        let (rx, resync) = listener.registry.lock().unwrap().register(
+            &callback.path,
+            &sid,
+            &service.service_id,
+            recorder,
+        );
+        let state = Arc::new(Mutex::new(SubscriptionState { sid, timeout }));
+        let renewal = tokio::spawn(keep_alive(
+            state.clone(),
+            resync,
+            service.event_sub_url.clone(),
+            callback.clone(),
+            transport.clone(),
+            listener.registry.clone(),
+        ));
+        Self {
+            state,
+            path: callback.path,
+            service_id: service.service_id.clone(),
+            event_sub_url: service.event_sub_url.clone(),
+            transport,
+            listener,
+            rx,
+            renewal,
+            active: true,
+        }
+    }
+
+    /// Current SID, which changes when the device forgot the subscription and it
+    /// had to be made again.
+    pub fn sid(&self) -> String {
+        self.state.lock().unwrap().sid.clone()
+    }
+
+    /// Duration granted by the device, `None` when it is infinite.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.state.lock().unwrap().timeout
+    }
+
+    pub fn service_id(&self) -> &str {
+        &self.service_id
+    }
+
+    /// Hands a NOTIFY to this subscription as if the listener had received it, SEQ
+    /// checks included.
+    pub(crate) fn deliver(&self, headers: Vec<(String, String)>, body: String) {
+        let seq = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("SEQ"))
+            .and_then(|(_, seq)| seq.trim().parse().ok());
+        let sid = self.sid();
+        self.listener.registry().lock().unwrap().notify(
+            &self.path,
+            &sid,
+            Notification { headers, seq, body },
+        );
+    }
+
+    pub async fn unsubscribe(mut self) -> Result<()> {
+        self.active = false;
+        self.renewal.abort();
+        let sid = self.sid();
+        self.listener
+            .registry
+            .lock()
+            .unwrap()
+            .unregister(&self.path, &sid);
+        send_unsubscribe(self.transport.as_ref(), &self.event_sub_url, &sid).await
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        self.renewal.abort();
+        let sid = self.sid();
+        self.listener
+            .registry
+            .lock()
+            .unwrap()
+            .unregister(&self.path, &sid);
+        if let Ok(handle) = Handle::try_current() {
+            let transport = self.transport.clone();
+            let event_sub_url = self.event_sub_url.clone();
+            handle.spawn(async move {
+                send_unsubscribe(transport.as_ref(), &event_sub_url, &sid)
+                    .await
+                    .ok();
+            });
+        }
+    }
+}
+
+/// Renews the subscription halfway through its timeout, and subscribes again when
+/// the device no longer knows the SID or events were missed. A failed renewal is
+/// retried with a growing backoff that stays within the remaining lifetime.
+async fn keep_alive(
+    state: Arc<Mutex<SubscriptionState>>,
+    resync: Arc<Notify>,
+    event_sub_url: String,
+    callback: Callback,
+    transport: Arc<dyn HttpTransport>,
+    registry: SharedRegistry,
+) {
+    let mut expires = state
+        .lock()
+        .unwrap()
+        .timeout
+        .map(|timeout| Instant::now() + timeout);
+    let mut retry: Option<Duration> = None;
+    loop {
+        let timeout = state.lock().unwrap().timeout;
+        let delay = match (retry, timeout) {
+            (Some(backoff), _) => Some(backoff),
+            (None, Some(timeout)) => Some(timeout / 2),
+            (None, None) => None,
+        }
+        .map(|delay| delay.max(MIN_RENEWAL_DELAY));
+        let renew = async {
+            match delay {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => future::pending().await,
+            }
+        };
+        let resyncing = tokio::select! {
+            _ = renew => false,
+            _ = resync.notified() => true,
+        };
+
+        let sid = state.lock().unwrap().sid.clone();
+        let result = if resyncing {
+            send_unsubscribe(transport.as_ref(), &event_sub_url, &sid)
+                .await
+                .ok();
+            resubscribe(
+                &state,
+                &event_sub_url,
+                &callback,
+                &transport,
+                &registry,
+                |previous_sid, sid| Event::Resynced { previous_sid, sid },
+            )
+            .await
+        } else {
+            match send_renew(transport.as_ref(), &event_sub_url, &sid).await {
+                Ok(timeout) => {
+                    state.lock().unwrap().timeout = timeout;
+                    Ok(())
+                }
+                Err(Error::HttpStatus { status: 412, .. }) => {
+                    resubscribe(
+                        &state,
+                        &event_sub_url,
+                        &callback,
+                        &transport,
+                        &registry,
+                        |previous_sid, sid| Event::Resubscribed { previous_sid, sid },
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                expires = state
+                    .lock()
+                    .unwrap()
+                    .timeout
+                    .map(|timeout| Instant::now() + timeout);
+                retry = None;
+            }
+            Err(e) => {
+                let backoff = retry.map_or(RENEWAL_RETRY, |backoff| {
+                    (backoff * 2).min(RENEWAL_RETRY_LIMIT)
+                });
+                let remaining = expires
+                    .map(|expires| expires.saturating_duration_since(Instant::now()))
+                    .unwrap_or(backoff);
+                retry = Some(if remaining.is_zero() {
+                    backoff
+                } else {
+                    backoff.min(remaining / 2)
+                });
+                registry.lock().unwrap().dispatch(
+                    &callback.path,
+                    &sid,
+                    Event::RenewalFailed {
+                        sid: sid.clone(),
+                        error: e.to_string(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Replaces the subscription with a new one, `announce` gets the previous and the
+/// new SID.
+async fn resubscribe(
+    state: &Mutex<SubscriptionState>,
+    event_sub_url: &str,
+    callback: &Callback,
+    transport: &Arc<dyn HttpTransport>,
+    registry: &SharedRegistry,
+    announce: fn(String, String) -> Event,
+) -> Result<()> {
+    let previous_sid = state.lock().unwrap().sid.clone();
+    // released when the renewal task is aborted while waiting for the device too
+    let _in_flight = Registry::begin_subscribe(registry);
+    let (sid, timeout) = send_subscribe(transport.as_ref(), event_sub_url, &callback.url).await?;
+    *state.lock().unwrap() = SubscriptionState {
+        sid: sid.clone(),
+        timeout,
+    };
+    let event = announce(previous_sid.clone(), sid.clone());
+    registry
+        .lock()
+        .unwrap()
+        .rekey(&callback.path, &previous_sid, &sid, event);
+    Ok(())
+}
+
+fn check_status(res: HttpResponse) -> Result<HttpResponse> {
+    if !res.is_success() {
+        return Err(Error::HttpStatus {
+            status: res.status,
+            body: res.body,
+        });
+    }
+    Ok(res)
+}
+
+pub(crate) async fn send_subscribe(
+    transport: &dyn HttpTransport,
+    event_sub_url: &str,
+    callback: &str,
+) -> Result<(String, Option<Duration>)> {
+    let user_agent = format!(
+        "upnp-client/{} ({})",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS
+    );
+    let req = HttpRequest::new("SUBSCRIBE", event_sub_url)
+        .header("CALLBACK", callback)
+        .header("NT", "upnp:event")
+        .header("TIMEOUT", SUBSCRIPTION_TIMEOUT)
+        .header("USER-AGENT", &user_agent);
+    let res = check_status(transport.send(req).await?)?;
+    let sid = res
+        .header("SID")
+        .ok_or_else(|| Error::Eventing("SUBSCRIBE response missing SID".to_string()))?;
+    Ok((sid.to_string(), granted_timeout(&res)))
+}
+
+async fn send_renew(
+    transport: &dyn HttpTransport,
+    event_sub_url: &str,
+    sid: &str,
+) -> Result<Option<Duration>> {
+    let req = HttpRequest::new("SUBSCRIBE", event_sub_url)
+        .header("SID", sid)
+        .header("TIMEOUT", SUBSCRIPTION_TIMEOUT);
+    let res = check_status(transport.send(req).await?)?;
+    Ok(granted_timeout(&res))
+}
+
+/// Timeout the device granted, `None` when infinite. A missing or unreadable
+/// `TIMEOUT` is taken as the one requested, so that the subscription is renewed.
+fn granted_timeout(res: &HttpResponse) -> Option<Duration> {
+    match res.header("TIMEOUT").map(parse_subscription_timeout) {
+        Some(Ok(timeout)) => timeout,
+        _ => Some(REQUESTED_TIMEOUT),
+    }
+}
+
+pub(crate) async fn send_unsubscribe(
+    transport: &dyn HttpTransport,
+    event_sub_url: &str,
+    sid: &str,
+) -> Result<()> {
+    let req = HttpRequest::new("UNSUBSCRIBE", event_sub_url).header("SID", sid);
+    check_status(transport.send(req).await?)?;
+    Ok(())
+}
+
+struct Notification {
+    headers: Vec<(String, String)>,
+    seq: Option<u32>,
+    body: String,
+}
+
+struct Route {
+    service_id: String,
+    recorder: Option<Recorder>,
+    tx: UnboundedSender<Event>,
+    last_seq: Option<u32>,
+    resync: Arc<Notify>,
+}
+
+enum Sequence {
+    Next,
+    Stale,
+    Gap,
+}
+
+fn sequence(last_seq: Option<u32>, seq: u32) -> Sequence {
+    match last_seq {
+        // the first SEQ after (re)subscribing is taken as is, some devices start at 1
+        None => Sequence::Next,
+        Some(_) if seq == 0 => Sequence::Stale,
+        // SEQ wraps to 1, 0 is only used for the initial event
+        Some(last) if seq == last.checked_add(1).unwrap_or(1) => Sequence::Next,
+        Some(last) if last.wrapping_sub(seq) < u32::MAX / 2 => Sequence::Stale,
+        Some(_) => Sequence::Gap,
+    }
+}
+
+/// Callback path and SID. Every subscription has its own callback path so that
+/// devices handing out the same SIDs do not mix up.
+type RouteKey = (String, String);
+
+fn key(path: &str, sid: &str) -> RouteKey {
+    (path.to_string(), sid.to_string())
+}
+
+/// Routes events to their subscription by callback path and SID.
+///
+/// A device may send the initial NOTIFY before we got to read the SID from the
+/// SUBSCRIBE response, so events for unknown SIDs are held back while a
+/// subscription is in flight.
+///
+/// Duplicate and late notifications are dropped by their SEQ, a missed one asks
+/// the subscription to resync.
+#[derive(Default)]
+pub(crate) struct Registry {
+    routes: HashMap<RouteKey, Route>,
+    pending: HashMap<RouteKey, Vec<Notification>>,
+    in_flight: usize,
+}
+
+pub(crate) type SharedRegistry = Arc<Mutex<Registry>>;
+
+/// Marks a SUBSCRIBE as in flight until dropped, also when the subscribing future
+/// is cancelled half way.
+pub(crate) struct InFlight {
+    registry: SharedRegistry,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = self.registry.lock() {
+            registry.in_flight -= 1;
+            if registry.in_flight == 0 {
+                registry.pending.clear();
+            }
+        }
+    }
+}
+
+impl Registry {
+    /// Holds back NOTIFYs for unknown SIDs until the returned guard is dropped, after
+    /// the subscription is registered or failed.
+    pub fn begin_subscribe(registry: &SharedRegistry) -> InFlight {
+        registry.lock().unwrap().in_flight += 1;
+        InFlight {
+            registry: registry.clone(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        path: &str,
+        sid: &str,
+        service_id: &str,
+        recorder: Option<Recorder>,
+    ) -> (UnboundedReceiver<Event>, Arc<Notify>) {
+        let (tx, rx) = unbounded_channel();
+        let resync = Arc::new(Notify::new());
+        let route = Route {
+            service_id: service_id.to_string(),
+            recorder,
+            tx,
+            last_seq: None,
+            resync: resync.clone(),
+        };
+        self.routes.insert(key(path, sid), route);
+        self.flush_pending(path, sid);
+        (rx, resync)
+    }
+
+    /// Moves the stream of `old_sid` over to the SID of a new subscription,
+    /// announcing it with `event`.
+    pub fn rekey(&mut self, path: &str, old_sid: &str, new_sid: &str, event: Event) {
+        if let Some(mut route) = self.routes.remove(&key(path, old_sid)) {
+            route.tx.send(event).ok();
+            route.last_seq = None;
+            self.routes.insert(key(path, new_sid), route);
+            self.flush_pending(path, new_sid);
+        }
+    }
+
+    pub fn unregister(&mut self, path: &str, sid: &str) {
+        self.routes.remove(&key(path, sid));
+    }
+
+    /// Sends an event raised by the client itself rather than by a NOTIFY.
+    pub fn dispatch(&mut self, path: &str, sid: &str, event: Event) {
+        if let Some(route) = self.routes.get(&key(path, sid)) {
+            if route.tx.send(event).is_err() {
+                self.routes.remove(&key(path, sid));
+            }
+        }
+    }
+
+    /// Whether a NOTIFY for `sid` belongs to a subscription, or may while one is in flight.
+    pub fn accepts(&self, path: &str, sid: &str) -> bool {
+        self.routes.contains_key(&key(path, sid)) || self.in_flight > 0
+    }
+
+    fn notify(&mut self, path: &str, sid: &str, notification: Notification) {
+        let route = match self.routes.get_mut(&key(path, sid)) {
+            Some(route) => route,
+            None => {
+                if self.in_flight > 0 {
+                    self.pending
+                        .entry(key(path, sid))
+                        .or_default()
+                        .push(notification);
+                }
+                return;
+            }
+        };
+
+        let Notification { headers, seq, body } = notification;
+        if let Some(recorder) = &route.recorder {
+            recorder.record(Exchange::Notify {
+                headers,
+                body: body.clone(),
+            });
+        }
+
+        if let Some(seq) = seq {
+            match sequence(route.last_seq, seq) {
+                Sequence::Next => route.last_seq = Some(seq),
+                Sequence::Stale => return,
+                Sequence::Gap => {
+                    route.last_seq = Some(seq);
+                    route.resync.notify_one();
+                }
+            }
+        }
+
+        for event in parse_events(sid, &route.service_id, &body) {
+            if route.tx.send(event).is_err() {
+                self.routes.remove(&key(path, sid));
+                return;
+            }
+        }
+    }
+
+    fn flush_pending(&mut self, path: &str, sid: &str) {
+        for notification in self.pending.remove(&key(path, sid)).unwrap_or_default() {
+            self.notify(path, sid, notification);
+        }
+    }
+}
+
+/// Local address of the interface that routes to `remote`, found by connecting a
+/// UDP socket, which sends nothing.
+pub(crate) fn local_address_to(remote: SocketAddr) -> Result<IpAddr> {
+    let bind: SocketAddr = match remote {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.connect(remote)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Binds `[::]:port` accepting both IPv6 and IPv4-mapped connections.
+fn bind_dual_stack(port: u16) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(false)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+static LISTENERS: OnceLock<Mutex<HashMap<u16, ListenerSlot>>> = OnceLock::new();
+
+/// A started listener, kept after it is dropped so that its port is only bound again
+/// once the thread serving it has let go of the socket.
+struct ListenerSlot {
+    listener: Weak<Listener>,
+    /// Closed when the thread serving the listener is done.
+    stopped: watch::Receiver<()>,
+}
+
+/// HTTP server receiving the NOTIFY requests of every subscription that asked for
+/// the same port, routed by SID.
+///
+/// It runs on its own thread so that it outlives the runtime of any single client,
+/// and shuts down once the last subscription using it is gone.
+pub(crate) struct Listener {
+    address: SocketAddr,
+    registry: SharedRegistry,
+    next_path: AtomicUsize,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl Listener {
+    /// The running listener for `port`, started when there is none. `None` picks
+    /// a free port.
+    pub async fn acquire(port: Option<u16>) -> Result<Arc<Listener>> {
+        let port = port.unwrap_or(0);
+        let stopping = {
+            let listeners = LISTENERS.get_or_init(Default::default).lock().unwrap();
+            match listeners.get(&port) {
+                Some(slot) => match slot.listener.upgrade() {
+                    Some(listener) => return Ok(listener),
+                    // a free port is picked anew, only a fixed one has to be let go of
+                    None if port != 0 => Some(slot.stopped.clone()),
+                    None => None,
+                },
+                None => None,
+            }
+        };
+        if let Some(mut stopped) = stopping {
+            // a device stuck in a NOTIFY holds the old listener up, bind anyway then
+            tokio::time::timeout(LISTENER_STOP_TIMEOUT, stopped.changed())
+                .await
+                .ok();
+        }
+
+        let mut listeners = LISTENERS.get_or_init(Default::default).lock().unwrap();
+        if let Some(listener) = listeners
+            .get(&port)
+            .and_then(|slot| slot.listener.upgrade())
+        {
+            return Ok(listener);
+        }
+        let (listener, stopped) = Listener::start(port)?;
+        let listener = Arc::new(listener);
+        listeners.insert(
+            port,
+            ListenerSlot {
+                listener: Arc::downgrade(&listener),
+                stopped,
+            },
+        );
+        Ok(listener)
+    }
+
+    fn start(port: u16) -> Result<(Listener, watch::Receiver<()>)> {
+        let listener = bind_dual_stack(port).or_else(|_| TcpListener::bind(("0.0.0.0", port)))?;
+        let address = listener.local_addr()?;
+        let registry = SharedRegistry::default();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let server = {
+            let _guard = runtime.enter();
+            let registry = registry.clone();
+            let service = make_service_fn(move |_: &AddrStream| {
+                let registry = registry.clone();
+                async move {
+                    Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                        handle_notify(req, registry.clone())
+                    }))
+                }
+            });
+            Server::from_tcp(listener)?
+                .serve(service)
+                .with_graceful_shutdown(async {
+                    stopped.await.ok();
+                })
+        };
+        let (done, stopped_serving) = watch::channel(());
+        thread::Builder::new()
+            .name("upnp-eventing".to_string())
+            .spawn(move || {
+                runtime.block_on(server).ok();
+                drop(runtime);
+                drop(done);
+            })?;
+
+        let listener = Listener {
+            address,
+            registry,
+            next_path: AtomicUsize::new(0),
+            shutdown: Some(shutdown),
+        };
+        Ok((listener, stopped_serving))
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// Whether devices can send events over IPv6, the listener falls back to IPv4
+    /// only where IPv6 is unavailable.
+    pub fn accepts_ipv6(&self) -> bool {
+        self.address.is_ipv6()
+    }
+
+    pub fn registry(&self) -> &SharedRegistry {
+        &self.registry
+    }
+
+    /// A callback path no other subscription on this listener uses.
+    pub fn callback_path(&self) -> String {
+        format!("/{}", self.next_path.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Accepts a GENA NOTIFY, answering 400 and 412 the way the UPnP Device Architecture
+/// asks for missing or invalid headers and unknown SIDs.
+async fn handle_notify(
+    req: Request<Body>,
+    registry: SharedRegistry,
+) -> std::result::Result<Response<Body>, hyper::Error> {
+    if req.method().as_str() != "NOTIFY" {
+        return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let path = req.uri().path().to_string();
+
+    let headers: Vec<(String, String)> = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).to_string(),
+            )
+        })
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    };
+
+    let sid = match (header("NT"), header("NTS"), header("SID")) {
+        (None, _, _) | (_, None, _) => return Ok(status(StatusCode::BAD_REQUEST)),
+        (Some(nt), Some(nts), Some(sid)) if nt == "upnp:event" && nts == "upnp:propchange" => sid,
+        _ => return Ok(status(StatusCode::PRECONDITION_FAILED)),
+    };
+    let seq = header("SEQ").and_then(|seq| seq.parse().ok());
+
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let body = String::from_utf8_lossy(&body).to_string();
+
+    let mut registry = registry.lock().unwrap();
+    if !registry.accepts(&path, &sid) {
+        return Ok(status(StatusCode::PRECONDITION_FAILED));
+    }
+    registry.notify(&path, &sid, Notification { headers, seq, body });
+
+    Ok(Response::new(Body::empty()))
+}
+
+fn invalid_notification(sid: &str, error: Error) -> Event {
+    Event::InvalidNotification {
+        sid: sid.to_string(),
+        error: error.to_string(),
+    }
+}
+
+fn parse_events(sid: &str, service_id: &str, xml: &str) -> Vec<Event> {
+    let values = match parse_property_set(xml) {
+        Ok(values) => values,
+        Err(e) => return vec![invalid_notification(sid, e)],
+    };
+    let last_change = values
+        .iter()
+        .find(|(name, _)| name == "LastChange")
+        .map(|(_, value)| value.clone());
+    let mut events = vec![Event::StateVariables {
+        sid: sid.to_string(),
+        service_id: service_id.to_string(),
+        values,
+    }];
+
+    let last_change = match last_change {
+        Some(last_change)
+            if service_id.ends_with(":AVTransport")
+                || service_id.ends_with(":RenderingControl") =>
+        {
+            parse_last_change_values(&last_change)
+        }
+        _ => return events,
+    };
+    match last_change {
+        Ok(values) if service_id.ends_with(":AVTransport") => {
+            events.extend(parse_av_transport_events(sid, values));
+        }
+        Ok(values) => events.extend(parse_rendering_control_events(sid, values)),
+        Err(e) => events.push(invalid_notification(sid, e)),
+    }
+    events
+}
+
+fn parse_rendering_control_events(sid: &str, values: Vec<LastChangeValue>) -> Vec<Event> {
+    values
+        .into_iter()
+        .map(|value| {
+            let sid = sid.to_string();
+            let instance_id = value.instance_id;
+            let channel = value.channel.clone().unwrap_or("Master".to_string());
+            let event = match value.name.as_str() {
+                "Volume" => value
+                    .value
+                    .parse()
+                    .ok()
+                    .map(|volume| RenderingControlEvent::Volume {
+                        sid: sid.clone(),
+                        instance_id,
+                        channel,
+                        volume,
+                    }),
+                "VolumeDB" => {
+                    value
+                        .value
+                        .parse()
+                        .ok()
+                        .map(|volume_db| RenderingControlEvent::VolumeDB {
+                            sid: sid.clone(),
+                            instance_id,
+                            channel,
+                            volume_db,
+                        })
+                }
+                "Mute" => parse_bool(&value.value)
+                    .ok()
+                    .map(|mute| RenderingControlEvent::Mute {
+                        sid: sid.clone(),
+                        instance_id,
+                        channel,
+                        mute,
+                    }),
+                "Loudness" => {
+                    parse_bool(&value.value)
+                        .ok()
+                        .map(|loudness| RenderingControlEvent::Loudness {
+                            sid: sid.clone(),
+                            instance_id,
+                            channel,
+                            loudness,
+                        })
+                }
+                "PresetNameList" => Some(RenderingControlEvent::PresetNameList {
+                    sid: sid.clone(),
+                    instance_id,
+                    presets: split_list(&value.value),
+                }),
+                _ => None,
+            };
+            event.unwrap_or(RenderingControlEvent::Other {
+                sid,
+                instance_id,
+                name: value.name,
+                channel: value.channel,
+                value: value.value,
+            })
+        })
+        .map(Event::RenderingControl)
+        .collect()
+}
+
+fn parse_av_transport_events(sid: &str, values: Vec<LastChangeValue>) -> Vec<Event> {
+    values
+        .into_iter()
+        .map(|value| {
+            let sid = sid.to_string();
+            let instance_id = value.instance_id;
+            let text = value.value.clone();
+            let event = match value.name.as_str() {
+                "TransportState" => Some(AVTransportEvent::TransportState {
+                    sid: sid.clone(),
+                    instance_id,
+                    transport_state: TransportState::from(text.as_str()),
+                }),
+                "TransportStatus" => Some(AVTransportEvent::TransportStatus {
+                    sid: sid.clone(),
+                    instance_id,
+                    transport_status: TransportStatus::from(text.as_str()),
+                }),
+                "PlaybackStorageMedium" => Some(AVTransportEvent::PlaybackStorageMedium {
+                    sid: sid.clone(),
+                    instance_id,
+                    medium: text,
+                }),
+                "RecordStorageMedium" => Some(AVTransportEvent::RecordStorageMedium {
+                    sid: sid.clone(),
+                    instance_id,
+                    medium: text,
+                }),
+                "PossiblePlaybackStorageMedia" => {
+                    Some(AVTransportEvent::PossiblePlaybackStorageMedia {
+                        sid: sid.clone(),
+                        instance_id,
+                        media: split_list(&text),
+                    })
+                }
+                "PossibleRecordStorageMedia" => {
+                    Some(AVTransportEvent::PossibleRecordStorageMedia {
+                        sid: sid.clone(),
+                        instance_id,
+                        media: split_list(&text),
+                    })
+                }
+                "CurrentPlayMode" => Some(AVTransportEvent::CurrentPlayMode {
+                    sid: sid.clone(),
+                    instance_id,
+                    play_mode: PlayMode::from(text.as_str()),
+                }),
+                "TransportPlaySpeed" => Some(AVTransportEvent::TransportPlaySpeed {
+                    sid: sid.clone(),
+                    instance_id,
+                    speed: text,
+                }),
+                "RecordMediumWriteStatus" => Some(AVTransportEvent::RecordMediumWriteStatus {
+                    sid: sid.clone(),
+                    instance_id,
+                    status: text,
+                }),
+                "CurrentRecordQualityMode" => Some(AVTransportEvent::CurrentRecordQualityMode {
+                    sid: sid.clone(),
+                    instance_id,
+                    mode: text,
+                }),
+                "PossibleRecordQualityModes" => {
+                    Some(AVTransportEvent::PossibleRecordQualityModes {
+                        sid: sid.clone(),
+                        instance_id,
+                        modes: split_list(&text),
+                    })
+                }
+                "NumberOfTracks" => text.trim().parse().ok().map(|number_of_tracks| {
+                    AVTransportEvent::NumberOfTracks {
+                        sid: sid.clone(),
+                        instance_id,
+                        number_of_tracks,
+                    }
+                }),
+                "CurrentTrack" => {
+                    text.trim()
+                        .parse()
+                        .ok()
+                        .map(|track| AVTransportEvent::CurrentTrack {
+                            sid: sid.clone(),
+                            instance_id,
+                            track,
+                        })
+                }
+                "CurrentTrackDuration" => Some(AVTransportEvent::CurrentTrackDuration {
+                    sid: sid.clone(),
+                    instance_id,
+                    duration: text,
+                }),
+                "CurrentMediaDuration" => Some(AVTransportEvent::CurrentMediaDuration {
+                    sid: sid.clone(),
+                    instance_id,
+                    duration: text,
+                }),
+                "CurrentTrackURI" => Some(AVTransportEvent::CurrentTrackURI {
+                    sid: sid.clone(),
+                    instance_id,
+                    uri: text,
+                }),
+                "CurrentTrackMetaData" => deserialize_metadata(&text).ok().map(|m| {
+                    AVTransportEvent::CurrentTrackMetadata {
+                        sid: sid.clone(),
+                        instance_id,
+                        url: m.url,
+                        title: m.title,
+                        artist: m.artist,
+                        album: m.album,
+                        album_art_uri: m.album_art_uri,
+                        genre: m.genre,
+                    }
+                }),
+                "AVTransportURI" => Some(AVTransportEvent::AVTransportURI {
+                    sid: sid.clone(),
+                    instance_id,
+                    uri: text,
+                }),
+                "AVTransportURIMetaData" => deserialize_metadata(&text).ok().map(|m| {
+                    AVTransportEvent::AVTransportURIMetaData {
+                        sid: sid.clone(),
+                        instance_id,
+                        url: m.url,
+                        title: m.title,
+                        artist: m.artist,
+                        album: m.album,
+                        album_art_uri: m.album_art_uri,
+                        genre: m.genre,
+                    }
+                }),
+                "NextAVTransportURI" => Some(AVTransportEvent::NextAVTransportURI {
+                    sid: sid.clone(),
+                    instance_id,
+                    uri: text,
+                }),
+                "NextAVTransportURIMetaData" => deserialize_metadata(&text).ok().map(|m| {
+                    AVTransportEvent::NextAVTransportURIMetaData {
+                        sid: sid.clone(),
+                        instance_id,
+                        url: m.url,
+                        title: m.title,
+                        artist: m.artist,
+                        album: m.album,
+                        album_art_uri: m.album_art_uri,
+                        genre: m.genre,
+                    }
+                }),
+                "CurrentTransportActions" => Some(AVTransportEvent::CurrentTransportActions {
+                    sid: sid.clone(),
+                    instance_id,
+                    actions: split_list(&text),
+                }),
+                "RelativeTimePosition" => Some(AVTransportEvent::RelativeTimePosition {
+                    sid: sid.clone(),
+                    instance_id,
+                    position: text,
+                }),
+                "AbsoluteTimePosition" => Some(AVTransportEvent::AbsoluteTimePosition {
+                    sid: sid.clone(),
+                    instance_id,
+                    position: text,
+                }),
+                "RelativeCounterPosition" => text.trim().parse().ok().map(|position| {
+                    AVTransportEvent::RelativeCounterPosition {
+                        sid: sid.clone(),
+                        instance_id,
+                        position,
+                    }
+                }),
+                "AbsoluteCounterPosition" => text.trim().parse().ok().map(|position| {
+                    AVTransportEvent::AbsoluteCounterPosition {
+                        sid: sid.clone(),
+                        instance_id,
+                        position,
+                    }
+                }),
+                _ => None,
+            };
+            event.unwrap_or(AVTransportEvent::Other {
+                sid,
+                instance_id,
+                name: value.name,
+                value: value.value,
+            })
+        })
+        .map(Event::AVTransport)
+        .collect()
+}
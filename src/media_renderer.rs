@@ -1,27 +1,101 @@
-use std::{collections::HashMap, sync::mpsc};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use anyhow::{Error, Ok};
 use async_stream::stream;
-use futures_util::Stream;
+use futures_util::{future, Stream, StreamExt};
+use tokio::{
+    sync::watch,
+    time::{Instant, MissedTickBehavior},
+};
 use xml_builder::{XMLBuilder, XMLElement};
 
 use crate::{
     device_client::DeviceClient,
+    eventing::Subscription,
     parser::{
-        parse_duration, parse_position, parse_supported_protocols, parse_transport_info,
-        parse_volume,
+        parse_bool, parse_media_info, parse_position_info, parse_response_argument, parse_speed,
+        parse_supported_protocols, parse_time, parse_transport_info, parse_volume, split_list,
     },
-    types::{Event, LoadOptions, Metadata, ObjectClass, TransportInfo},
-    BROADCAST_EVENT,
+    types::{
+        AVTransportEvent, AllowedRange, DeviceCapabilities, Event, LoadOptions, MediaInfo,
+        Metadata, ObjectClass, PictureControl, PlayMode, PositionInfo, Progress, RendererState,
+        RenderingControlEvent, SeekTarget, TransportInfo, TransportSettings, TransportState,
+        TransportStatus,
+    },
+    Error,
 };
 
+/// How often `position_stream` asks the renderer where it is, at most.
+const POSITION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a renderer that stays at the end of the track is polled again.
+const END_OF_TRACK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Shortest interval callers may ask polling at.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Shortest time between two volume changes of a fade.
+const FADE_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Playback events of a renderer, see `MediaRendererClient::media_events`.
+#[derive(Debug, Clone, PartialEq)]
 pub enum MediaEvents {
-    Status,
+    Status(TransportStatus),
     Loading,
     Playing,
     Paused,
     Stopped,
-    SpeedChanged,
+    SpeedChanged(String),
+    TrackChanged(String),
+    PlayModeChanged(PlayMode),
+}
+
+/// AVTransport and RenderingControl events of a renderer, merged in one stream.
+pub struct RendererEvents {
+    av_transport: Subscription,
+    rendering_control: Subscription,
+    poll_rendering_control_first: bool,
+}
+
+impl RendererEvents {
+    pub fn av_transport(&self) -> &Subscription {
+        &self.av_transport
+    }
+
+    pub fn rendering_control(&self) -> &Subscription {
+        &self.rendering_control
+    }
+
+    pub async fn unsubscribe(self) -> Result<(), Error> {
+        let av_transport = self.av_transport.unsubscribe().await;
+        self.rendering_control.unsubscribe().await?;
+        av_transport
+    }
+}
+
+impl Stream for RendererEvents {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        let this = &mut *self;
+        this.poll_rendering_control_first = !this.poll_rendering_control_first;
+        let (first, second) = if this.poll_rendering_control_first {
+            (&mut this.rendering_control, &mut this.av_transport)
+        } else {
+            (&mut this.av_transport, &mut this.rendering_control)
+        };
+        match Pin::new(first).poll_next(cx) {
+            Poll::Ready(Some(event)) => Poll::Ready(Some(event)),
+            Poll::Ready(None) => Pin::new(second).poll_next(cx),
+            Poll::Pending => match Pin::new(second).poll_next(cx) {
+                Poll::Ready(None) => Poll::Pending,
+                poll => poll,
+            },
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -37,31 +111,11 @@ impl MediaRendererClient {
         let dlna_features = options.dlna_features.unwrap_or("*".to_string());
         let content_type = options.content_type.unwrap_or("video/mpeg".to_string());
         let protocol_info = format!("http-get:*:{}:{}", content_type, dlna_features);
-        let title = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .title;
-        let artist = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .artist;
-        let album = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .album;
-        let album_art_uri = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .album_art_uri;
-        let genre = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .genre;
+        let title = options.metadata.clone().unwrap_or_default().title;
+        let artist = options.metadata.clone().unwrap_or_default().artist;
+        let album = options.metadata.clone().unwrap_or_default().album;
+        let album_art_uri = options.metadata.clone().unwrap_or_default().album_art_uri;
+        let genre = options.metadata.clone().unwrap_or_default().genre;
 
         let m = Metadata {
             url: url.to_string(),
@@ -111,16 +165,138 @@ impl MediaRendererClient {
     }
 
     pub async fn seek(&self, seconds: u64) -> Result<(), Error> {
+        self.seek_to(SeekTarget::RelTime(Duration::from_secs(seconds)))
+            .await
+    }
+
+    /// Fails with `Error::ActionNotAllowed` without sending the Seek when the
+    /// renderer's CurrentTransportActions rule seeking out.
+    pub async fn seek_to(&self, target: SeekTarget) -> Result<(), Error> {
+        if let Some(actions) = self.transport_actions().await? {
+            if !actions.iter().any(|action| allows_seek(action, &target)) {
+                return Err(Error::ActionNotAllowed("Seek".to_string()));
+            }
+        }
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
-        params.insert("Unit".to_string(), "REL_TIME".to_string());
-        params.insert("Target".to_string(), format_time(seconds));
+        params.insert("Unit".to_string(), target.unit().to_string());
+        params.insert("Target".to_string(), target.target());
         self.device_client
             .call_action("AVTransport", "Seek", params)
             .await?;
         Ok(())
     }
 
+    /// Seek `seconds` forward, or backward when negative, from the current position,
+    /// staying within the current track.
+    pub async fn seek_by(&self, seconds: f64) -> Result<(), Error> {
+        let not_allowed = || Error::ValueNotAllowed {
+            name: "seconds".to_string(),
+            value: seconds.to_string(),
+        };
+        if !seconds.is_finite() {
+            return Err(not_allowed());
+        }
+        let position = self.get_position_info().await?;
+        let current = position
+            .rel_time
+            .ok_or_else(|| Error::Parse("GetPositionInfo response missing RelTime".to_string()))?;
+        let mut target = (current.as_secs_f64() + seconds).max(0.0);
+        if let Some(duration) = position.track_duration.filter(|d| !d.is_zero()) {
+            target = target.min(duration.as_secs_f64());
+        }
+        let target = Duration::try_from_secs_f64(target).map_err(|_| not_allowed())?;
+        self.seek_to(SeekTarget::RelTime(target)).await
+    }
+
+    /// Actions the renderer currently allows, `None` when it does not tell, or
+    /// answers with nothing, `NOT_IMPLEMENTED` or an error.
+    async fn transport_actions(&self) -> Result<Option<Vec<String>>, Error> {
+        match self.get_current_transport_actions().await {
+            Ok(actions) if actions.is_empty() => Ok(None),
+            Ok(actions) => Ok(Some(actions)),
+            Err(Error::ActionNotFound(_))
+            | Err(Error::Parse(_))
+            | Err(Error::SoapFault { .. })
+            | Err(Error::HttpStatus { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn get_current_transport_actions(&self) -> Result<Vec<String>, Error> {
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        let response = self
+            .device_client
+            .call_action("AVTransport", "GetCurrentTransportActions", params)
+            .await?;
+        let actions = parse_response_argument(&response, "Actions")?.ok_or_else(|| {
+            Error::Parse("GetCurrentTransportActions response missing Actions".to_string())
+        })?;
+        Ok(split_list(&actions))
+    }
+
+    /// Fails with `Error::ValueNotAllowed` when the renderer does not advertise `mode`.
+    pub async fn set_play_mode(&self, mode: PlayMode) -> Result<(), Error> {
+        self.check_allowed("AVTransport", "CurrentPlayMode", mode.value())
+            .await?;
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        params.insert("NewPlayMode".to_string(), mode.value().to_string());
+        self.device_client
+            .call_action("AVTransport", "SetPlayMode", params)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_transport_settings(&self) -> Result<TransportSettings, Error> {
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        let response = self
+            .device_client
+            .call_action("AVTransport", "GetTransportSettings", params)
+            .await?;
+        let play_mode = parse_response_argument(&response, "PlayMode")?.ok_or_else(|| {
+            Error::Parse("GetTransportSettings response missing PlayMode".to_string())
+        })?;
+        Ok(TransportSettings {
+            play_mode: PlayMode::from(play_mode.as_str()),
+            rec_quality_mode: parse_response_argument(&response, "RecQualityMode")?
+                .filter(|mode| !mode.is_empty() && mode != "NOT_IMPLEMENTED"),
+        })
+    }
+
+    pub async fn get_device_capabilities(&self) -> Result<DeviceCapabilities, Error> {
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        let response = self
+            .device_client
+            .call_action("AVTransport", "GetDeviceCapabilities", params)
+            .await?;
+        let list = |name: &str| -> Result<Vec<String>, Error> {
+            Ok(parse_response_argument(&response, name)?
+                .map(|value| split_list(&value))
+                .unwrap_or_default())
+        };
+        Ok(DeviceCapabilities {
+            play_media: list("PlayMedia")?,
+            rec_media: list("RecMedia")?,
+            rec_quality_modes: list("RecQualityModes")?,
+        })
+    }
+
+    /// Checks `value` against the allowed values and range of a state variable,
+    /// anything goes for variables the renderer does not describe.
+    async fn check_allowed(&self, service_id: &str, name: &str, value: &str) -> Result<(), Error> {
+        match self.device_client.state_variable(service_id, name).await? {
+            Some(variable) if !variable.allows(value) => Err(Error::ValueNotAllowed {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
     pub async fn stop(&self) -> Result<(), Error> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
@@ -152,31 +328,11 @@ impl MediaRendererClient {
         let dlna_features = options.dlna_features.unwrap_or("*".to_string());
         let content_type = options.content_type.unwrap_or("video/mpeg".to_string());
         let protocol_info = format!("http-get:*:{}:{}", content_type, dlna_features);
-        let title = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .title;
-        let artist = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .artist;
-        let album = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .album;
-        let album_art_uri = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .album_art_uri;
-        let genre = options
-            .metadata
-            .clone()
-            .unwrap_or(Metadata::default())
-            .genre;
+        let title = options.metadata.clone().unwrap_or_default().title;
+        let artist = options.metadata.clone().unwrap_or_default().artist;
+        let album = options.metadata.clone().unwrap_or_default().album;
+        let album_art_uri = options.metadata.clone().unwrap_or_default().album_art_uri;
+        let genre = options.metadata.clone().unwrap_or_default().genre;
 
         let m = Metadata {
             url: url.to_string(),
@@ -202,29 +358,307 @@ impl MediaRendererClient {
     }
 
     pub async fn get_volume(&self) -> Result<u8, Error> {
+        self.get_channel_volume("Master").await
+    }
+
+    pub async fn set_volume(&self, volume: u32) -> Result<(), Error> {
+        self.set_channel_volume("Master", volume).await
+    }
+
+    pub async fn get_channel_volume(&self, channel: &str) -> Result<u8, Error> {
+        let response = self
+            .call_rendering_control("GetVolume", channel, None)
+            .await?;
+        parse_volume(response.as_str())
+    }
+
+    /// Fails with `Error::ValueNotAllowed` outside of the advertised channels and
+    /// Volume range.
+    pub async fn set_channel_volume(&self, channel: &str, volume: u32) -> Result<(), Error> {
+        self.check_allowed("RenderingControl", "Volume", &volume.to_string())
+            .await?;
+        self.call_rendering_control(
+            "SetVolume",
+            channel,
+            Some(("DesiredVolume", volume.to_string())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Range of Volume advertised by the renderer, 0 to 100 when it does not say.
+    pub async fn get_volume_range(&self) -> Result<AllowedRange, Error> {
+        let volume = self
+            .device_client
+            .state_variable("RenderingControl", "Volume")
+            .await?;
+        Ok(volume
+            .and_then(|volume| volume.allowed_range)
+            .unwrap_or(AllowedRange {
+                minimum: 0,
+                maximum: 100,
+                step: Some(1),
+            }))
+    }
+
+    /// Raise the Master volume by `step`, up to the advertised maximum, and tell the
+    /// new volume.
+    pub async fn volume_up(&self, step: u32) -> Result<u32, Error> {
+        self.change_volume(step as i64).await
+    }
+
+    /// Lower the Master volume by `step`, down to the advertised minimum, and tell the
+    /// new volume.
+    pub async fn volume_down(&self, step: u32) -> Result<u32, Error> {
+        self.change_volume(-(step as i64)).await
+    }
+
+    async fn change_volume(&self, delta: i64) -> Result<u32, Error> {
+        let range = self.get_volume_range().await?;
+        let current = self.get_volume().await? as i64;
+        let volume = (current + delta).clamp(range.minimum, range.maximum) as u32;
+        if volume as i64 != current {
+            self.set_volume(volume).await?;
+        }
+        Ok(volume)
+    }
+
+    /// Fade the Master volume to `target`, within the advertised range, over
+    /// `duration`. Tells the volume the renderer ends up at, which differs from
+    /// `target` on renderers that clamp. Dropping the future cancels the fade and
+    /// leaves the volume where it got to.
+    pub async fn fade_volume(&self, target: u32, duration: Duration) -> Result<u32, Error> {
+        let from = self.get_volume().await?;
+        self.fade_volume_from(from as u32, target, duration).await
+    }
+
+    async fn fade_volume_from(
+        &self,
+        from: u32,
+        target: u32,
+        duration: Duration,
+    ) -> Result<u32, Error> {
+        let range = self.get_volume_range().await?;
+        let target = (target as i64).clamp(range.minimum, range.maximum);
+        fade(from as i64, target, duration, |volume| {
+            self.set_volume(volume as u32)
+        })
+        .await?;
+        Ok(self.get_volume().await? as u32)
+    }
+
+    /// Like `fade_volume`, with the Master volume in 1/256 dB.
+    pub async fn fade_volume_db(&self, target: i16, duration: Duration) -> Result<i16, Error> {
+        let (minimum, maximum) = self.get_volume_db_range().await?;
+        if minimum > maximum {
+            return Err(Error::Parse(
+                "GetVolumeDBRange MinValue is above MaxValue".to_string(),
+            ));
+        }
+        let from = self.get_volume_db().await?;
+        fade(
+            from as i64,
+            target.clamp(minimum, maximum) as i64,
+            duration,
+            |volume| self.set_volume_db(volume as i16),
+        )
+        .await?;
+        self.get_volume_db().await
+    }
+
+    /// Fade out over `duration`, stop, and put the volume back for whatever plays
+    /// next. Cancelling before the fade ends does not stop playback.
+    pub async fn fade_out_and_stop(&self, duration: Duration) -> Result<(), Error> {
+        let volume = self.get_volume().await?;
+        let range = self.get_volume_range().await?;
+        self.fade_volume_from(volume as u32, range.minimum as u32, duration)
+            .await?;
+        self.stop().await?;
+        self.set_volume(volume as u32).await
+    }
+
+    /// Start playing silently and fade in over `duration` to the volume the renderer
+    /// was at.
+    pub async fn play_and_fade_in(&self, duration: Duration) -> Result<u32, Error> {
+        let volume = self.get_volume().await?;
+        let range = self.get_volume_range().await?;
+        self.set_volume(range.minimum as u32).await?;
+        self.play().await?;
+        self.fade_volume_from(range.minimum as u32, volume as u32, duration)
+            .await
+    }
+
+    pub async fn get_mute(&self) -> Result<bool, Error> {
+        let response = self
+            .call_rendering_control("GetMute", "Master", None)
+            .await?;
+        let mute = parse_response_argument(&response, "CurrentMute")?
+            .ok_or_else(|| Error::Parse("GetMute response missing CurrentMute".to_string()))?;
+        parse_bool(&mute)
+    }
+
+    pub async fn set_mute(&self, mute: bool) -> Result<(), Error> {
+        let mute = if mute { "1" } else { "0" };
+        self.call_rendering_control("SetMute", "Master", Some(("DesiredMute", mute.to_string())))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_loudness(&self) -> Result<bool, Error> {
+        let response = self
+            .call_rendering_control("GetLoudness", "Master", None)
+            .await?;
+        let loudness = parse_response_argument(&response, "CurrentLoudness")?.ok_or_else(|| {
+            Error::Parse("GetLoudness response missing CurrentLoudness".to_string())
+        })?;
+        parse_bool(&loudness)
+    }
+
+    pub async fn set_loudness(&self, loudness: bool) -> Result<(), Error> {
+        let loudness = if loudness { "1" } else { "0" };
+        self.call_rendering_control(
+            "SetLoudness",
+            "Master",
+            Some(("DesiredLoudness", loudness.to_string())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Master volume in 1/256 dB.
+    pub async fn get_volume_db(&self) -> Result<i16, Error> {
+        let response = self
+            .call_rendering_control("GetVolumeDB", "Master", None)
+            .await?;
+        let volume = parse_response_argument(&response, "CurrentVolume")?.ok_or_else(|| {
+            Error::Parse("GetVolumeDB response missing CurrentVolume".to_string())
+        })?;
+        Ok(volume.trim().parse::<i16>()?)
+    }
+
+    /// Set the Master volume in 1/256 dB, within `get_volume_db_range`.
+    pub async fn set_volume_db(&self, volume: i16) -> Result<(), Error> {
+        self.check_allowed("RenderingControl", "VolumeDB", &volume.to_string())
+            .await?;
+        self.call_rendering_control(
+            "SetVolumeDB",
+            "Master",
+            Some(("DesiredVolume", volume.to_string())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Lowest and highest Master volume in 1/256 dB.
+    pub async fn get_volume_db_range(&self) -> Result<(i16, i16), Error> {
+        let response = self
+            .call_rendering_control("GetVolumeDBRange", "Master", None)
+            .await?;
+        let value = |name: &str| -> Result<i16, Error> {
+            let value = parse_response_argument(&response, name)?.ok_or_else(|| {
+                Error::Parse(format!("GetVolumeDBRange response missing {}", name))
+            })?;
+            Ok(value.trim().parse::<i16>()?)
+        };
+        Ok((value("MinValue")?, value("MaxValue")?))
+    }
+
+    pub async fn list_presets(&self) -> Result<Vec<String>, Error> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
-        params.insert("Channel".to_string(), "Master".to_string());
-
         let response = self
             .device_client
-            .call_action("RenderingControl", "GetVolume", params)
+            .call_action("RenderingControl", "ListPresets", params)
             .await?;
+        let presets =
+            parse_response_argument(&response, "CurrentPresetNameList")?.ok_or_else(|| {
+                Error::Parse("ListPresets response missing CurrentPresetNameList".to_string())
+            })?;
+        Ok(split_list(&presets))
+    }
 
-        Ok(parse_volume(response.as_str())?)
+    pub async fn select_preset(&self, name: &str) -> Result<(), Error> {
+        self.check_allowed("RenderingControl", "A_ARG_TYPE_PresetName", name)
+            .await?;
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        params.insert("PresetName".to_string(), name.to_string());
+        self.device_client
+            .call_action("RenderingControl", "SelectPreset", params)
+            .await?;
+        Ok(())
     }
 
-    pub async fn set_volume(&self, volume: u32) -> Result<(), Error> {
+    pub async fn get_picture_control(&self, control: PictureControl) -> Result<i32, Error> {
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        let response = self
+            .device_client
+            .call_action(
+                "RenderingControl",
+                &format!("Get{}", control.value()),
+                params,
+            )
+            .await?;
+        let name = format!("Current{}", control.value());
+        let value = parse_response_argument(&response, &name)?.ok_or_else(|| {
+            Error::Parse(format!("Get{} response missing {}", control.value(), name))
+        })?;
+        Ok(value.trim().parse::<i32>()?)
+    }
+
+    /// Fails with `Error::ValueNotAllowed` outside of `get_picture_control_range`.
+    pub async fn set_picture_control(
+        &self,
+        control: PictureControl,
+        value: i32,
+    ) -> Result<(), Error> {
+        self.check_allowed("RenderingControl", control.value(), &value.to_string())
+            .await?;
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
-        params.insert("Channel".to_string(), "Master".to_string());
-        params.insert("DesiredVolume".to_string(), volume.to_string());
+        params.insert(format!("Desired{}", control.value()), value.to_string());
         self.device_client
-            .call_action("RenderingControl", "SetVolume", params)
+            .call_action(
+                "RenderingControl",
+                &format!("Set{}", control.value()),
+                params,
+            )
             .await?;
         Ok(())
     }
 
+    /// Range the renderer advertises for `control`, `None` when it does not say.
+    pub async fn get_picture_control_range(
+        &self,
+        control: PictureControl,
+    ) -> Result<Option<AllowedRange>, Error> {
+        let variable = self
+            .device_client
+            .state_variable("RenderingControl", control.value())
+            .await?;
+        Ok(variable.and_then(|variable| variable.allowed_range))
+    }
+
+    async fn call_rendering_control(
+        &self,
+        action: &str,
+        channel: &str,
+        argument: Option<(&str, String)>,
+    ) -> Result<String, Error> {
+        self.check_allowed("RenderingControl", "A_ARG_TYPE_Channel", channel)
+            .await?;
+        let mut params = HashMap::new();
+        params.insert("InstanceID".to_string(), "0".to_string());
+        params.insert("Channel".to_string(), channel.to_string());
+        if let Some((name, value)) = argument {
+            params.insert(name.to_string(), value);
+        }
+        self.device_client
+            .call_action("RenderingControl", action, params)
+            .await
+    }
+
     pub async fn get_supported_protocols(&self) -> Result<Vec<String>, Error> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
@@ -232,41 +666,159 @@ impl MediaRendererClient {
             .device_client
             .call_action("ConnectionManager", "GetProtocolInfo", params)
             .await?;
-        Ok(parse_supported_protocols(response.as_str())?)
+        parse_supported_protocols(response.as_str())
     }
 
+    /// Position in the current track, in whole seconds.
     pub async fn get_position(&self) -> Result<u32, Error> {
+        self.get_position_info()
+            .await?
+            .rel_time
+            .map(|position| position.as_secs() as u32)
+            .ok_or_else(|| Error::Parse("GetPositionInfo response missing RelTime".to_string()))
+    }
+
+    /// Duration of the current media, in whole seconds.
+    pub async fn get_duration(&self) -> Result<u32, Error> {
+        self.get_media_info()
+            .await?
+            .media_duration
+            .map(|duration| duration.as_secs() as u32)
+            .ok_or_else(|| Error::Parse("GetMediaInfo response missing MediaDuration".to_string()))
+    }
+
+    pub async fn get_position_info(&self) -> Result<PositionInfo, Error> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         let response = self
             .device_client
             .call_action("AVTransport", "GetPositionInfo", params)
             .await?;
-        Ok(parse_position(response.as_str())?)
+        parse_position_info(response.as_str())
     }
 
-    pub async fn get_duration(&self) -> Result<u32, Error> {
+    pub async fn get_media_info(&self) -> Result<MediaInfo, Error> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
         let response = self
             .device_client
             .call_action("AVTransport", "GetMediaInfo", params)
             .await?;
-        Ok(parse_duration(response.as_str())?)
+        parse_media_info(response.as_str())
     }
 
-    pub async fn subscribe(&mut self) -> impl Stream<Item = Event> {
-        let (tx, rx) = mpsc::channel();
-        *BROADCAST_EVENT.lock().unwrap() = Some(tx);
+    pub async fn subscribe(&self) -> Result<Subscription, Error> {
+        self.device_client.subscribe("AVTransport").await
+    }
+
+    pub async fn subscribe_rendering_control(&self) -> Result<Subscription, Error> {
+        self.device_client.subscribe("RenderingControl").await
+    }
+
+    /// Subscribe to both AVTransport and RenderingControl.
+    pub async fn subscribe_all(&self) -> Result<RendererEvents, Error> {
+        let av_transport = self.subscribe().await?;
+        let rendering_control = self.subscribe_rendering_control().await?;
+        Ok(RendererEvents {
+            av_transport,
+            rendering_control,
+            poll_rendering_control_first: false,
+        })
+    }
+
+    /// Playback events of instance 0, from an AVTransport subscription that ends
+    /// when the stream is dropped.
+    pub async fn media_events(&self) -> Result<impl Stream<Item = MediaEvents>, Error> {
+        let subscription = self.subscribe().await?;
+        Ok(subscription.filter_map(|event| future::ready(media_event(&event))))
+    }
+
+    /// Mirror of what the renderer is doing, kept up to date from its events and by
+    /// polling the position, which is not evented, every `poll_interval`, 10ms at the
+    /// least. Mirroring stops once every receiver is dropped.
+    pub async fn watch_state(
+        &self,
+        poll_interval: Duration,
+    ) -> Result<watch::Receiver<RendererState>, Error> {
+        let mut events = self.subscribe_all().await?;
+        let mut state = RendererState::default();
+        if let Ok(info) = self.get_transport_info().await {
+            state.transport_state = Some(info.current_transport_state);
+        }
+        state.volume = self.get_volume().await.ok();
+        if let Ok(info) = self.get_position_info().await {
+            state.current_uri = info.track_uri.filter(|uri| !uri.is_empty());
+            state.duration = info.track_duration.filter(|duration| !duration.is_zero());
+        }
+        let (tx, rx) = watch::channel(state);
+
+        let client = self.clone();
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(poll_interval.max(MIN_POLL_INTERVAL));
+            poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    event = events.next() => match event {
+                        Some(event) => {
+                            tx.send_if_modified(|state| apply_event(state, &event));
+                        }
+                        None => break,
+                    },
+                    _ = poll.tick() => {
+                        if let Ok(position) = client.get_position().await {
+                            let position = Duration::from_secs(position as u64);
+                            tx.send_if_modified(|state| update(&mut state.position, position));
+                        }
+                    }
+                }
+            }
+        });
 
-        self.device_client.subscribe("AVTransport").await.unwrap();
+        Ok(rx)
+    }
+
+    /// Progress of the current track every `interval`, 10ms at the least. The
+    /// renderer is only polled every few seconds, or when the track should have
+    /// ended, positions in between are interpolated from the transport state and
+    /// play speed.
+    pub fn position_stream(&self, interval: Duration) -> impl Stream<Item = Progress> {
+        let client = self.clone();
         stream! {
-            while let Some(event) = rx.recv().into_iter().next() {
-                yield event;
+            let interval = interval.max(MIN_POLL_INTERVAL);
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let max_age = interval.max(POSITION_POLL_INTERVAL);
+            let end_age = interval.max(END_OF_TRACK_POLL_INTERVAL);
+            let mut sample: Option<PositionSample> = None;
+            loop {
+                ticks.tick().await;
+                if sample.as_ref().is_none_or(|sample| sample.is_stale(max_age, end_age)) {
+                    if let Ok(polled) = client.sample_position().await {
+                        sample = Some(polled);
+                    }
+                }
+                if let Some(sample) = &sample {
+                    yield sample.progress();
+                }
             }
         }
     }
 
+    async fn sample_position(&self) -> Result<PositionSample, Error> {
+        let info = self.get_transport_info().await?;
+        let position = self.get_position_info().await?;
+        Ok(PositionSample {
+            position: position.rel_time.unwrap_or_default(),
+            duration: position
+                .track_duration
+                .filter(|duration| !duration.is_zero()),
+            playing: info.current_transport_state == TransportState::Playing,
+            speed: parse_speed(&info.current_speed).unwrap_or(1.0),
+            at: Instant::now(),
+        })
+    }
+
     pub async fn get_transport_info(&self) -> Result<TransportInfo, Error> {
         let mut params = HashMap::new();
         params.insert("InstanceID".to_string(), "0".to_string());
@@ -274,7 +826,193 @@ impl MediaRendererClient {
             .device_client
             .call_action("AVTransport", "GetTransportInfo", params)
             .await?;
-        Ok(parse_transport_info(response.as_str())?)
+        parse_transport_info(response.as_str())
+    }
+}
+
+/// Position reported by the renderer, and what is needed to extrapolate it.
+struct PositionSample {
+    position: Duration,
+    duration: Option<Duration>,
+    playing: bool,
+    speed: f64,
+    at: Instant,
+}
+
+impl PositionSample {
+    fn position(&self) -> Duration {
+        let mut position = self.position;
+        if self.playing {
+            let elapsed = self.at.elapsed().as_secs_f64() * self.speed;
+            position = Duration::from_secs_f64((position.as_secs_f64() + elapsed).max(0.0));
+        }
+        match self.duration {
+            Some(duration) => position.min(duration),
+            None => position,
+        }
+    }
+
+    /// Whether the renderer should be polled again, because the sample is older than
+    /// `max_age`, or the track should have ended, or rewound to its start, and the
+    /// sample is older than `end_age`.
+    fn is_stale(&self, max_age: Duration, end_age: Duration) -> bool {
+        let age = self.at.elapsed();
+        if age >= max_age {
+            return true;
+        }
+        let position = self.position();
+        age >= end_age
+            && self.playing
+            && ((self.speed > 0.0 && self.duration == Some(position))
+                || (self.speed < 0.0 && position.is_zero()))
+    }
+
+    fn progress(&self) -> Progress {
+        let position = self.position();
+        Progress {
+            position,
+            duration: self.duration,
+            percentage: self
+                .duration
+                .map(|duration| position.as_secs_f64() / duration.as_secs_f64() * 100.0),
+        }
+    }
+}
+
+fn media_event(event: &Event) -> Option<MediaEvents> {
+    let Event::AVTransport(event) = event else {
+        return None;
+    };
+    match event {
+        AVTransportEvent::TransportState {
+            instance_id: 0,
+            transport_state,
+            ..
+        } => match transport_state {
+            TransportState::Transitioning => Some(MediaEvents::Loading),
+            TransportState::Playing => Some(MediaEvents::Playing),
+            TransportState::PausedPlayback | TransportState::PausedRecording => {
+                Some(MediaEvents::Paused)
+            }
+            TransportState::Stopped | TransportState::NoMediaPresent => Some(MediaEvents::Stopped),
+            TransportState::Recording | TransportState::Other(_) => None,
+        },
+        AVTransportEvent::TransportStatus {
+            instance_id: 0,
+            transport_status,
+            ..
+        } => Some(MediaEvents::Status(transport_status.clone())),
+        AVTransportEvent::TransportPlaySpeed {
+            instance_id: 0,
+            speed,
+            ..
+        } => Some(MediaEvents::SpeedChanged(speed.clone())),
+        AVTransportEvent::CurrentTrackURI {
+            instance_id: 0,
+            uri,
+            ..
+        } if !uri.is_empty() => Some(MediaEvents::TrackChanged(uri.clone())),
+        AVTransportEvent::CurrentPlayMode {
+            instance_id: 0,
+            play_mode,
+            ..
+        } => Some(MediaEvents::PlayModeChanged(play_mode.clone())),
+        _ => None,
+    }
+}
+
+fn update<T: PartialEq>(field: &mut Option<T>, value: T) -> bool {
+    if field.as_ref() == Some(&value) {
+        return false;
+    }
+    *field = Some(value);
+    true
+}
+
+/// Applies an event of instance 0 to `state`, telling whether anything changed.
+fn apply_event(state: &mut RendererState, event: &Event) -> bool {
+    match event {
+        Event::AVTransport(event) => match event {
+            AVTransportEvent::TransportState {
+                instance_id: 0,
+                transport_state,
+                ..
+            } => update(&mut state.transport_state, transport_state.clone()),
+            AVTransportEvent::CurrentTrackURI {
+                instance_id: 0,
+                uri,
+                ..
+            } if !uri.is_empty() => update(&mut state.current_uri, uri.clone()),
+            AVTransportEvent::AVTransportURIMetaData {
+                instance_id: 0,
+                url,
+                title,
+                artist,
+                album,
+                album_art_uri,
+                genre,
+                ..
+            }
+            | AVTransportEvent::CurrentTrackMetadata {
+                instance_id: 0,
+                url,
+                title,
+                artist,
+                album,
+                album_art_uri,
+                genre,
+                ..
+            } => {
+                let metadata = Metadata {
+                    url: url.clone(),
+                    title: title.clone(),
+                    artist: artist.clone(),
+                    album: album.clone(),
+                    album_art_uri: album_art_uri.clone(),
+                    genre: genre.clone(),
+                    protocol_info: String::new(),
+                };
+                update(&mut state.metadata, metadata)
+            }
+            AVTransportEvent::CurrentTrackDuration {
+                instance_id: 0,
+                duration,
+                ..
+            } => match parse_time(duration) {
+                Some(duration) if !duration.is_zero() => update(&mut state.duration, duration),
+                _ => false,
+            },
+            AVTransportEvent::RelativeTimePosition {
+                instance_id: 0,
+                position,
+                ..
+            } => match parse_time(position) {
+                Some(position) => update(&mut state.position, position),
+                None => false,
+            },
+            AVTransportEvent::CurrentPlayMode {
+                instance_id: 0,
+                play_mode,
+                ..
+            } => update(&mut state.play_mode, play_mode.clone()),
+            _ => false,
+        },
+        Event::RenderingControl(event) => match event {
+            RenderingControlEvent::Volume {
+                instance_id: 0,
+                channel,
+                volume,
+                ..
+            } if channel == "Master" => update(&mut state.volume, *volume),
+            RenderingControlEvent::Mute {
+                instance_id: 0,
+                channel,
+                mute,
+                ..
+            } if channel == "Master" => update(&mut state.mute, *mute),
+            _ => false,
+        },
+        _ => false,
     }
 }
 
@@ -344,9 +1082,39 @@ fn build_metadata(m: Metadata, media_type: ObjectClass) -> String {
     xml::escape::escape_str_attribute(&metadata).to_string()
 }
 
-fn format_time(seconds: u64) -> String {
-    let hours = seconds / 3600;
-    let minutes = (seconds % 3600) / 60;
-    let seconds = seconds % 60;
-    format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+/// Steps from `from` to `to` over `duration`, sending a value whenever the rounded
+/// value changes. Values follow the elapsed time, so renderers that are slow to
+/// answer get fewer, larger steps rather than a longer fade.
+async fn fade<F, Fut>(from: i64, to: i64, duration: Duration, mut set: F) -> Result<(), Error>
+where
+    F: FnMut(i64) -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let start = Instant::now();
+    let mut last = from;
+    while last != to {
+        let progress = match duration.is_zero() {
+            true => 1.0,
+            false => (start.elapsed().as_secs_f64() / duration.as_secs_f64()).min(1.0),
+        };
+        let value = from + ((to - from) as f64 * progress).round() as i64;
+        if value != last {
+            set(value).await?;
+            last = value;
+        }
+        if last != to {
+            tokio::time::sleep(FADE_STEP_INTERVAL).await;
+        }
+    }
+    Ok(())
+}
+
+// DLNA renderers may only allow seeking by time or by byte
+fn allows_seek(action: &str, target: &SeekTarget) -> bool {
+    match action {
+        "Seek" => true,
+        "X_DLNA_SeekTime" => matches!(target, SeekTarget::AbsTime(_) | SeekTarget::RelTime(_)),
+        "X_DLNA_SeekByte" => matches!(target, SeekTarget::RelByte(_)),
+        _ => false,
+    }
 }
@@ -4,8 +4,8 @@ use crate::{
     device_client::DeviceClient,
     parser::parse_browse_response,
     types::{Container, Item},
+    Error,
 };
-use anyhow::Error;
 
 #[derive(Clone)]
 pub struct MediaServerClient {
@@ -1,22 +1,19 @@
-use std::str::Split;
+use std::collections::HashMap;
 use std::time::Duration;
 
-use crate::types::{Action, Argument, Container, Device, Item, Metadata, Service, TransportInfo};
-use anyhow::{anyhow, Result};
+use crate::transport::{HttpRequest, HttpTransport};
+use crate::types::{
+    Action, AllowedRange, Argument, Container, Device, Item, LastChangeValue, MediaInfo, Metadata,
+    PositionInfo, Service, StateVariable, TransportInfo, TransportState, TransportStatus,
+};
+use crate::{Error, Result};
 use elementtree::Element;
-use surf::{http::Method, Client, Config, Url};
+use url::Url;
 use xml::reader::XmlEvent;
 use xml::EventReader;
 
-pub async fn parse_location(location: &str) -> Result<Device> {
-    let client: Client = Config::new()
-        .set_timeout(Some(Duration::from_secs(5)))
-        .try_into()?;
-    let req = surf::Request::new(Method::Get, location.parse()?);
-    let xml_root = client
-        .recv_string(req)
-        .await
-        .map_err(|e| anyhow!("Failed to retrieve xml from device endpoint: {}", e))?;
+pub async fn parse_location(location: &str, transport: &dyn HttpTransport) -> Result<Device> {
+    let xml_root = fetch_xml(location, transport).await?;
 
     let mut device = Device {
         location: location.to_string(),
@@ -71,29 +68,38 @@ pub async fn parse_location(location: &str) -> Result<Device> {
     )?;
 
     let base_url = location.split('/').take(3).collect::<Vec<&str>>().join("/");
-    device.services = parse_services(&base_url, &xml_root).await?;
+    device.services = parse_services(&base_url, &xml_root, transport).await?;
 
     Ok(device)
 }
 
+async fn fetch_xml(url: &str, transport: &dyn HttpTransport) -> Result<String> {
+    let res = transport.send(HttpRequest::new("GET", url)).await?;
+    if !res.is_success() {
+        return Err(Error::HttpStatus {
+            status: res.status,
+            body: res.body,
+        });
+    }
+    Ok(res.body)
+}
+
 fn parse_attribute(xml_root: &str, xml_name: &str) -> Result<String> {
     let root = Element::from_reader(xml_root.as_bytes())?;
     let mut xml_name = xml_name.split('/');
     match root.find(
         xml_name
             .next()
-            .ok_or_else(|| anyhow!("xml_name ended unexpectedly"))?,
+            .ok_or_else(|| Error::Parse("xml_name ended unexpectedly".to_string()))?,
     ) {
         Some(element) => {
             let element = element.find(
                 xml_name
                     .next()
-                    .ok_or_else(|| anyhow!("xml_name ended unexpectedly"))?,
+                    .ok_or_else(|| Error::Parse("xml_name ended unexpectedly".to_string()))?,
             );
             match element {
-                Some(element) => {
-                    return Ok(element.text().to_string());
-                }
+                Some(element) => Ok(element.text().to_string()),
                 None => Ok("".to_string()),
             }
         }
@@ -101,11 +107,15 @@ fn parse_attribute(xml_root: &str, xml_name: &str) -> Result<String> {
     }
 }
 
-pub async fn parse_services(base_url: &str, xml_root: &str) -> Result<Vec<Service>> {
+pub async fn parse_services(
+    base_url: &str,
+    xml_root: &str,
+    transport: &dyn HttpTransport,
+) -> Result<Vec<Service>> {
     let root = Element::from_reader(xml_root.as_bytes())?;
     let device = root
         .find("{urn:schemas-upnp-org:device-1-0}device")
-        .ok_or_else(|| anyhow!("Invalid response from device"))?;
+        .ok_or_else(|| Error::Parse("Device description missing device".to_string()))?;
 
     let mut services_with_actions: Vec<Service> = vec![];
     if let Some(service_list) = device.find("{urn:schemas-upnp-org:device-1-0}serviceList") {
@@ -116,30 +126,31 @@ pub async fn parse_services(base_url: &str, xml_root: &str) -> Result<Vec<Servic
             let mut service = Service {
                 service_type: xml_service
                     .find("{urn:schemas-upnp-org:device-1-0}serviceType")
-                    .ok_or_else(|| anyhow!("Service missing serviceType"))?
+                    .ok_or_else(|| Error::Parse("Service missing serviceType".to_string()))?
                     .text()
                     .to_string(),
                 service_id: xml_service
                     .find("{urn:schemas-upnp-org:device-1-0}serviceId")
-                    .ok_or_else(|| anyhow!("Service missing serviceId"))?
+                    .ok_or_else(|| Error::Parse("Service missing serviceId".to_string()))?
                     .text()
                     .to_string(),
                 control_url: xml_service
                     .find("{urn:schemas-upnp-org:device-1-0}controlURL")
-                    .ok_or_else(|| anyhow!("Service missing controlURL"))?
+                    .ok_or_else(|| Error::Parse("Service missing controlURL".to_string()))?
                     .text()
                     .to_string(),
                 event_sub_url: xml_service
                     .find("{urn:schemas-upnp-org:device-1-0}eventSubURL")
-                    .ok_or_else(|| anyhow!("Service missing eventSubURL"))?
+                    .ok_or_else(|| Error::Parse("Service missing eventSubURL".to_string()))?
                     .text()
                     .to_string(),
                 scpd_url: xml_service
                     .find("{urn:schemas-upnp-org:device-1-0}SCPDURL")
-                    .ok_or_else(|| anyhow!("Service missing SCPDURL"))?
+                    .ok_or_else(|| Error::Parse("Service missing SCPDURL".to_string()))?
                     .text()
                     .to_string(),
                 actions: vec![],
+                state_variables: vec![],
             };
 
             service.control_url = build_absolute_url(base_url, &service.control_url)?;
@@ -151,7 +162,8 @@ pub async fn parse_services(base_url: &str, xml_root: &str) -> Result<Vec<Servic
 
         for service in &services {
             let mut service = service.clone();
-            service.actions = parse_service_description(&service.scpd_url).await?;
+            (service.actions, service.state_variables) =
+                parse_service_description(&service.scpd_url, transport).await?;
             services_with_actions.push(service);
         }
     }
@@ -164,18 +176,16 @@ fn build_absolute_url(base_url: &str, relative_url: &str) -> Result<String> {
     Ok(base_url.join(relative_url)?.to_string())
 }
 
-pub async fn parse_service_description(scpd_url: &str) -> Result<Vec<Action>> {
-    let client: Client = Config::new()
-        .set_timeout(Some(Duration::from_secs(5)))
-        .try_into()?;
-    let req = surf::Request::new(Method::Get, scpd_url.parse()?);
-
-    let xml_root = client
-        .recv_string(req)
-        .await
-        .map_err(|e| anyhow!("Failed to retrieve xml response from device: {}", e))?;
+pub async fn parse_service_description(
+    scpd_url: &str,
+    transport: &dyn HttpTransport,
+) -> Result<(Vec<Action>, Vec<StateVariable>)> {
+    let xml_root = fetch_xml(scpd_url, transport).await?;
     let root = Element::from_reader(xml_root.as_bytes())?;
+    Ok((parse_actions(&root)?, parse_state_variables(&root)?))
+}
 
+fn parse_actions(root: &Element) -> Result<Vec<Action>> {
     let action_list = match root.find("{urn:schemas-upnp-org:service-1-0}actionList") {
         Some(action_list) => action_list,
         None => return Ok(vec![]),
@@ -186,7 +196,7 @@ pub async fn parse_service_description(scpd_url: &str) -> Result<Vec<Action>> {
         let mut action = Action {
             name: xml_action
                 .find("{urn:schemas-upnp-org:service-1-0}name")
-                .ok_or_else(|| anyhow!("Service::Action missing name"))?
+                .ok_or_else(|| Error::Parse("Service::Action missing name".to_string()))?
                 .text()
                 .to_string(),
             arguments: vec![],
@@ -197,18 +207,25 @@ pub async fn parse_service_description(scpd_url: &str) -> Result<Vec<Action>> {
                 let argument = Argument {
                     name: xml_argument
                         .find("{urn:schemas-upnp-org:service-1-0}name")
-                        .ok_or_else(|| anyhow!("Service::Action::Argument missing name"))?
+                        .ok_or_else(|| {
+                            Error::Parse("Service::Action::Argument missing name".to_string())
+                        })?
                         .text()
                         .to_string(),
                     direction: xml_argument
                         .find("{urn:schemas-upnp-org:service-1-0}direction")
-                        .ok_or_else(|| anyhow!("Service::Action::Argument missing direction"))?
+                        .ok_or_else(|| {
+                            Error::Parse("Service::Action::Argument missing direction".to_string())
+                        })?
                         .text()
                         .to_string(),
                     related_state_variable: xml_argument
                         .find("{urn:schemas-upnp-org:service-1-0}relatedStateVariable")
                         .ok_or_else(|| {
-                            anyhow!("Service::Action::Argument missing relatedStateVariable")
+                            Error::Parse(
+                                "Service::Action::Argument missing relatedStateVariable"
+                                    .to_string(),
+                            )
                         })?
                         .text()
                         .to_string(),
@@ -221,232 +238,404 @@ pub async fn parse_service_description(scpd_url: &str) -> Result<Vec<Action>> {
     Ok(actions)
 }
 
+fn parse_state_variables(root: &Element) -> Result<Vec<StateVariable>> {
+    let state_table = match root.find("{urn:schemas-upnp-org:service-1-0}serviceStateTable") {
+        Some(state_table) => state_table,
+        None => return Ok(vec![]),
+    };
+
+    let text = |element: &Element, name: &str| {
+        element
+            .find(format!("{{urn:schemas-upnp-org:service-1-0}}{}", name).as_str())
+            .map(|child| child.text().trim().to_string())
+    };
+
+    let mut state_variables = Vec::new();
+    for xml_variable in state_table.children() {
+        let allowed_values = xml_variable
+            .find("{urn:schemas-upnp-org:service-1-0}allowedValueList")
+            .map(|list| {
+                list.children()
+                    .map(|value| value.text().trim().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        // ranges that are not integers are of no use for the controls we expose,
+        // inverted ones are as good as none
+        let allowed_range = xml_variable
+            .find("{urn:schemas-upnp-org:service-1-0}allowedValueRange")
+            .and_then(|range| {
+                Some(AllowedRange {
+                    minimum: text(range, "minimum")?.parse().ok()?,
+                    maximum: text(range, "maximum")?.parse().ok()?,
+                    step: text(range, "step").and_then(|step| step.parse().ok()),
+                })
+            })
+            .filter(|range| range.minimum <= range.maximum);
+        state_variables.push(StateVariable {
+            name: text(xml_variable, "name")
+                .ok_or_else(|| Error::Parse("Service::StateVariable missing name".to_string()))?,
+            data_type: text(xml_variable, "dataType").unwrap_or_default(),
+            send_events: xml_variable.get_attr("sendEvents") != Some("no"),
+            default_value: text(xml_variable, "defaultValue"),
+            allowed_values,
+            allowed_range,
+        });
+    }
+    Ok(state_variables)
+}
+
 pub fn parse_volume(xml_root: &str) -> Result<u8> {
     let parser = EventReader::from_str(xml_root);
     let mut in_current_volume = false;
     let mut current_volume: Option<u8> = None;
     for e in parser {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "CurrentVolume" {
-                    in_current_volume = true;
-                }
+            Ok(XmlEvent::StartElement { name, .. }) if name.local_name == "CurrentVolume" => {
+                in_current_volume = true;
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "CurrentVolume" {
-                    in_current_volume = false;
-                }
+            Ok(XmlEvent::EndElement { name }) if name.local_name == "CurrentVolume" => {
+                in_current_volume = false;
             }
-            Ok(XmlEvent::Characters(volume)) => {
-                if in_current_volume {
-                    current_volume = Some(volume.parse()?);
-                }
+            Ok(XmlEvent::Characters(volume)) if in_current_volume => {
+                current_volume = Some(volume.parse()?);
             }
             _ => {}
         }
     }
-    current_volume.ok_or_else(|| anyhow!("Invalid response from device"))
+    current_volume
+        .ok_or_else(|| Error::Parse("GetVolume response missing CurrentVolume".to_string()))
 }
 
-pub fn parse_duration(xml_root: &str) -> Result<u32> {
+/// Parses a `H+:MM:SS[.F+]` or `H+:MM:SS[.F0/F1]` time, `None` for `NOT_IMPLEMENTED`
+/// and anything else that is not a time.
+pub fn parse_time(value: &str) -> Option<Duration> {
+    let value = value.trim().trim_start_matches('+');
+    let (time, fraction) = match value.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (value, None),
+    };
+    let mut parts = time.split(':');
+    let hours = parts.next()?.parse::<u64>().ok()?;
+    let minutes = parts.next()?.parse::<u64>().ok()?;
+    let seconds = parts.next()?.parse::<u64>().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let fraction = match fraction {
+        Some(fraction) => match fraction.split_once('/') {
+            Some((numerator, denominator)) => {
+                let numerator = numerator.parse::<f64>().ok()?;
+                let denominator = denominator.parse::<f64>().ok()?;
+                if denominator == 0.0 {
+                    return None;
+                }
+                numerator / denominator
+            }
+            None => format!("0.{}", fraction).parse::<f64>().ok()?,
+        },
+        None => 0.0,
+    };
+    if !fraction.is_finite() || fraction < 0.0 {
+        return None;
+    }
+    let seconds = hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)?
+        .checked_add(seconds)?;
+    Duration::from_secs(seconds).checked_add(Duration::try_from_secs_f64(fraction).ok()?)
+}
+
+/// Parses a `TransportPlaySpeed` such as `1`, `-2` or `1/2`.
+pub fn parse_speed(value: &str) -> Option<f64> {
+    let speed = match value.trim().split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator = denominator.parse::<f64>().ok()?;
+            if denominator == 0.0 {
+                return None;
+            }
+            numerator.parse::<f64>().ok()? / denominator
+        }
+        None => value.trim().parse::<f64>().ok()?,
+    };
+    speed.is_finite().then_some(speed)
+}
+
+pub fn parse_supported_protocols(xml_root: &str) -> Result<Vec<String>> {
     let parser = EventReader::from_str(xml_root);
-    let mut in_duration = false;
-    let mut duration: Option<String> = None;
+    let mut in_protocol = false;
+    let mut protocols: String = "".to_string();
     for e in parser {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "MediaDuration" {
-                    in_duration = true;
-                }
+            Ok(XmlEvent::StartElement { name, .. }) if name.local_name == "Sink" => {
+                in_protocol = true;
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "MediaDuration" {
-                    in_duration = false;
-                }
+            Ok(XmlEvent::EndElement { name }) if name.local_name == "Sink" => {
+                in_protocol = false;
             }
-            Ok(XmlEvent::Characters(duration_str)) => {
-                if in_duration {
-                    let duration_str = duration_str.replace(':', "");
-                    duration = Some(duration_str);
-                }
+            Ok(XmlEvent::Characters(protocol)) if in_protocol => {
+                protocols = protocol;
             }
             _ => {}
         }
     }
+    Ok(protocols.split(',').map(|s| s.to_string()).collect())
+}
 
-    let duration = duration.ok_or_else(|| anyhow!("Invalid response from device"))?;
-    let hours = duration[0..2].parse::<u32>()?;
-    let minutes = duration[2..4].parse::<u32>()?;
-    let seconds = duration[4..6].parse::<u32>()?;
-    Ok(hours * 3600 + minutes * 60 + seconds)
+pub fn parse_query_state_variable(xml_root: &str) -> Result<Option<String>> {
+    parse_response_argument(xml_root, "return")
 }
 
-pub fn parse_position(xml_root: &str) -> Result<u32> {
+/// Value of the `argument` out argument of an action response.
+pub fn parse_response_argument(xml_root: &str, argument: &str) -> Result<Option<String>> {
     let parser = EventReader::from_str(xml_root);
-    let mut in_position = false;
-    let mut position_iter: Split<'_, &str>;
-    let mut position: Option<String> = None;
+    let mut in_argument = false;
+    let mut result: Option<String> = None;
     for e in parser {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "RelTime" {
-                    in_position = true;
-                }
+            Ok(XmlEvent::StartElement { name, .. }) if name.local_name == argument => {
+                in_argument = true;
+                result = Some("".to_string());
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "RelTime" {
-                    in_position = false;
-                }
+            Ok(XmlEvent::EndElement { name }) if name.local_name == argument => {
+                in_argument = false;
             }
-            Ok(XmlEvent::Characters(position_str)) => {
-                if in_position {
-                    position = Some(position_str);
-                }
+            Ok(XmlEvent::Characters(value)) if in_argument => {
+                result = Some(value);
             }
             _ => {}
         }
     }
-
-    let position = position.ok_or_else(|| anyhow!("Invalid response from device"))?;
-    position_iter = position.split(":");
-    let hours = position_iter.next().unwrap_or("0").parse::<u32>()?;
-    let minutes = position_iter.next().unwrap_or("0").parse::<u32>()?;
-    let seconds = position_iter.next().unwrap_or("0").parse::<u32>()?;
-    Ok(hours * 3600 + minutes * 60 + seconds)
+    Ok(result)
 }
 
-pub fn parse_supported_protocols(xml_root: &str) -> Result<Vec<String>> {
+/// All out arguments of an action response, by name.
+pub fn parse_response_arguments(xml_root: &str) -> Result<HashMap<String, String>> {
     let parser = EventReader::from_str(xml_root);
-    let mut in_protocol = false;
-    let mut protocols: String = "".to_string();
+    let mut current: Option<String> = None;
+    let mut arguments = HashMap::new();
     for e in parser {
         match e {
             Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "Sink" {
-                    in_protocol = true;
-                }
+                arguments.insert(name.local_name.clone(), String::new());
+                current = Some(name.local_name);
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "Sink" {
-                    in_protocol = false;
-                }
+            Ok(XmlEvent::EndElement { .. }) => {
+                current = None;
             }
-            Ok(XmlEvent::Characters(protocol)) => {
-                if in_protocol {
-                    protocols = protocol;
+            Ok(XmlEvent::Characters(value)) => {
+                if let Some(name) = &current {
+                    arguments.insert(name.clone(), value);
                 }
             }
+            Err(e) => return Err(Error::Parse(e.to_string())),
             _ => {}
         }
     }
-    Ok(protocols.split(',').map(|s| s.to_string()).collect())
+    Ok(arguments)
+}
+
+// Empty and NOT_IMPLEMENTED values are both absent
+fn argument_value(arguments: &HashMap<String, String>, name: &str) -> Option<String> {
+    arguments
+        .get(name)
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty() && *value != "NOT_IMPLEMENTED")
+        .map(|value| value.to_string())
+}
+
+fn argument_metadata(arguments: &HashMap<String, String>, name: &str) -> Result<Option<Metadata>> {
+    argument_value(arguments, name)
+        .map(|didl| deserialize_metadata(&didl))
+        .transpose()
+}
+
+// 2147483647 is how counters say they are not implemented
+fn argument_count(arguments: &HashMap<String, String>, name: &str) -> Result<Option<i32>> {
+    match argument_value(arguments, name) {
+        Some(count) => Ok(Some(count.parse::<i32>()?).filter(|count| *count != i32::MAX)),
+        None => Ok(None),
+    }
 }
 
-pub fn parse_last_change(xml_root: &str) -> Result<Option<String>> {
+pub fn parse_position_info(xml_root: &str) -> Result<PositionInfo> {
+    let arguments = parse_response_arguments(xml_root)?;
+    Ok(PositionInfo {
+        track: argument_value(&arguments, "Track")
+            .map(|track| track.parse::<u32>())
+            .transpose()?
+            .unwrap_or_default(),
+        track_duration: argument_value(&arguments, "TrackDuration")
+            .and_then(|duration| parse_time(&duration)),
+        track_metadata: argument_metadata(&arguments, "TrackMetaData")?,
+        track_uri: argument_value(&arguments, "TrackURI"),
+        rel_time: argument_value(&arguments, "RelTime").and_then(|time| parse_time(&time)),
+        abs_time: argument_value(&arguments, "AbsTime").and_then(|time| parse_time(&time)),
+        rel_count: argument_count(&arguments, "RelCount")?,
+        abs_count: argument_count(&arguments, "AbsCount")?,
+    })
+}
+
+pub fn parse_media_info(xml_root: &str) -> Result<MediaInfo> {
+    let arguments = parse_response_arguments(xml_root)?;
+    Ok(MediaInfo {
+        nr_tracks: argument_value(&arguments, "NrTracks")
+            .map(|tracks| tracks.parse::<u32>())
+            .transpose()?
+            .unwrap_or_default(),
+        media_duration: argument_value(&arguments, "MediaDuration")
+            .and_then(|duration| parse_time(&duration)),
+        current_uri: argument_value(&arguments, "CurrentURI"),
+        current_uri_metadata: argument_metadata(&arguments, "CurrentURIMetaData")?,
+        next_uri: argument_value(&arguments, "NextURI"),
+        next_uri_metadata: argument_metadata(&arguments, "NextURIMetaData")?,
+        play_medium: argument_value(&arguments, "PlayMedium"),
+        record_medium: argument_value(&arguments, "RecordMedium"),
+        write_status: argument_value(&arguments, "WriteStatus"),
+    })
+}
+
+pub fn parse_upnp_error(xml_root: &str) -> Result<Option<(u32, String)>> {
     let parser = EventReader::from_str(xml_root);
-    let mut result = None;
-    let mut in_last_change = false;
+    let mut in_error_code = false;
+    let mut in_error_description = false;
+    let mut error_code: Option<u32> = None;
+    let mut error_description = String::from("");
     for e in parser {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "LastChange" {
-                    in_last_change = true;
-                }
-            }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "LastChange" {
-                    in_last_change = false;
+            Ok(XmlEvent::StartElement { name, .. }) => match name.local_name.as_str() {
+                "errorCode" => in_error_code = true,
+                "errorDescription" => in_error_description = true,
+                _ => {}
+            },
+            Ok(XmlEvent::EndElement { name }) => match name.local_name.as_str() {
+                "errorCode" => in_error_code = false,
+                "errorDescription" => in_error_description = false,
+                _ => {}
+            },
+            Ok(XmlEvent::Characters(value)) => {
+                if in_error_code {
+                    error_code = Some(value.trim().parse()?);
                 }
-            }
-            Ok(XmlEvent::Characters(last_change)) => {
-                if in_last_change {
-                    result = Some(last_change);
+                if in_error_description {
+                    error_description = value;
                 }
             }
             _ => {}
         }
     }
-    Ok(result)
+    Ok(error_code.map(|code| (code, error_description)))
 }
 
-pub fn parse_current_play_mode(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut current_play_mode: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
-            name, attributes, ..
-        } = e
-        {
-            if name.local_name == "CurrentPlayMode" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        current_play_mode = Some(attr.value);
-                    }
-                }
-            }
-        }
+/// Parses a GENA `TIMEOUT` header, `None` for `Second-infinite`.
+pub fn parse_subscription_timeout(timeout: &str) -> Result<Option<Duration>> {
+    match timeout.trim().strip_prefix("Second-") {
+        Some(seconds) if seconds.eq_ignore_ascii_case("infinite") => Ok(None),
+        Some(seconds) => Ok(Some(Duration::from_secs(seconds.parse()?))),
+        None => Err(Error::Parse(format!(
+            "Invalid subscription timeout {}",
+            timeout
+        ))),
     }
-    Ok(current_play_mode)
 }
 
-pub fn parse_transport_state(xml_root: &str) -> Result<Option<String>> {
+/// Name and value of every variable of a GENA `<e:propertyset>`, in document order.
+pub fn parse_property_set(xml_root: &str) -> Result<Vec<(String, String)>> {
     let parser = EventReader::from_str(xml_root);
-    let mut transport_state: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
-            name, attributes, ..
-        } = e
-        {
-            if name.local_name == "TransportState" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        transport_state = Some(attr.value);
-                    }
+    let mut values = vec![];
+    let mut in_property = false;
+    let mut variable: Option<(String, String)> = None;
+    for e in parser {
+        match e? {
+            XmlEvent::StartElement { name, .. } if name.local_name == "property" => {
+                in_property = true;
+            }
+            XmlEvent::EndElement { name } if name.local_name == "property" => {
+                in_property = false;
+            }
+            XmlEvent::StartElement { name, .. } if in_property && variable.is_none() => {
+                variable = Some((name.local_name, String::new()));
+            }
+            XmlEvent::EndElement { name }
+                if variable
+                    .as_ref()
+                    .is_some_and(|(variable, _)| *variable == name.local_name) =>
+            {
+                values.extend(variable.take());
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some((_, value)) = variable.as_mut() {
+                    value.push_str(&text);
                 }
             }
+            _ => {}
         }
     }
-    Ok(transport_state)
+    Ok(values)
 }
 
-pub fn parse_av_transport_uri_metadata(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut av_transport_uri_metadata: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
-            name, attributes, ..
-        } = e
-        {
-            if name.local_name == "AVTransportURIMetaData" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        av_transport_uri_metadata = Some(attr.value);
-                    }
-                }
+/// Every variable of a `LastChange` value, for all the instances it covers.
+pub fn parse_last_change_values(last_change: &str) -> Result<Vec<LastChangeValue>> {
+    let parser = EventReader::from_str(last_change);
+    let mut values = vec![];
+    let mut instance_id = None;
+    for e in parser {
+        match e? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if name.local_name == "InstanceID" => {
+                let val = attributes
+                    .iter()
+                    .find(|attr| attr.name.local_name == "val")
+                    .ok_or_else(|| Error::Parse("InstanceID without val".to_string()))?;
+                instance_id = Some(val.value.trim().parse::<u32>()?);
+            }
+            XmlEvent::EndElement { name } if name.local_name == "InstanceID" => {
+                instance_id = None;
+            }
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if instance_id.is_some() => {
+                let attribute = |attribute: &str| {
+                    attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == attribute)
+                        .map(|attr| attr.value.clone())
+                };
+                values.push(LastChangeValue {
+                    instance_id: instance_id.unwrap_or_default(),
+                    name: name.local_name,
+                    channel: attribute("channel"),
+                    value: attribute("val").unwrap_or_default(),
+                });
             }
+            _ => {}
         }
     }
-    Ok(av_transport_uri_metadata)
+    Ok(values)
 }
 
-pub fn parse_current_track_metadata(xml_root: &str) -> Result<Option<String>> {
-    let parser = EventReader::from_str(xml_root);
-    let mut current_track_metadata: Option<String> = None;
-    for e in parser.into_iter().flatten() {
-        if let XmlEvent::StartElement {
-            name, attributes, ..
-        } = e
-        {
-            if name.local_name == "CurrentTrackMetaData" {
-                for attr in attributes {
-                    if attr.name.local_name == "val" {
-                        current_track_metadata = Some(attr.value);
-                    }
-                }
-            }
+pub fn parse_bool(value: &str) -> Result<bool> {
+    match value.trim() {
+        "1" => Ok(true),
+        "0" => Ok(false),
+        value if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("yes") => {
+            Ok(true)
+        }
+        value if value.eq_ignore_ascii_case("false") || value.eq_ignore_ascii_case("no") => {
+            Ok(false)
         }
+        value => Err(Error::Parse(format!("Invalid boolean {}", value))),
     }
-    Ok(current_track_metadata)
+}
+
+/// Splits a comma separated list, where `NOT_IMPLEMENTED` is as good as empty.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty() && item != "NOT_IMPLEMENTED")
+        .collect()
 }
 
 pub fn deserialize_metadata(xml: &str) -> Result<Metadata> {
@@ -534,20 +723,14 @@ pub fn parse_browse_response(xml: &str, ip: &str) -> Result<(Vec<Container>, Vec
 
     for e in parser {
         match e {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                if name.local_name == "Result" {
-                    in_result = true;
-                }
+            Ok(XmlEvent::StartElement { name, .. }) if name.local_name == "Result" => {
+                in_result = true;
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "Result" {
-                    in_result = false;
-                }
+            Ok(XmlEvent::EndElement { name }) if name.local_name == "Result" => {
+                in_result = false;
             }
-            Ok(XmlEvent::Characters(value)) => {
-                if in_result {
-                    result = deserialize_content_directory(&value, ip)?;
-                }
+            Ok(XmlEvent::Characters(value)) if in_result => {
+                result = deserialize_content_directory(&value, ip)?;
             }
             _ => {}
         }
@@ -746,10 +929,10 @@ pub fn parse_transport_info(xml: &str) -> Result<TransportInfo> {
             }
             Ok(XmlEvent::Characters(value)) => {
                 if in_transport_state {
-                    transport_info.current_transport_state = value.clone();
+                    transport_info.current_transport_state = TransportState::from(value.as_str());
                 }
                 if in_transport_status {
-                    transport_info.current_transport_status = value.clone();
+                    transport_info.current_transport_status = TransportStatus::from(value.as_str());
                 }
                 if in_transport_play_speed {
                     transport_info.current_speed = value.clone();
@@ -763,11 +946,42 @@ pub fn parse_transport_info(xml: &str) -> Result<TransportInfo> {
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::parse_services;
+    use crate::{
+        parser::{
+            parse_last_change_values, parse_position_info, parse_property_set,
+            parse_query_state_variable, parse_services, parse_speed, parse_subscription_timeout,
+            parse_time, parse_upnp_error, split_list,
+        },
+        transport::{HttpRequest, HttpResponse, HttpTransport},
+        types::{LastChangeValue, Metadata, PositionInfo},
+    };
+    use crate::{Error, Result};
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    struct StaticTransport(Vec<(&'static str, &'static str)>);
+
+    #[async_trait]
+    impl HttpTransport for StaticTransport {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            let (_, body) = self
+                .0
+                .iter()
+                .find(|(url, _)| *url == request.url)
+                .ok_or_else(|| {
+                    Error::Transport(format!("Unexpected request to {}", request.url))
+                })?;
+            Ok(HttpResponse {
+                status: 200,
+                body: body.to_string(),
+                ..Default::default()
+            })
+        }
+    }
 
     #[tokio::test]
     async fn test_parsing_device_without_service_list() {
-        const XML_ROOT: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
         <root xmlns="urn:schemas-upnp-org:device-1-0">
             <specVersion>
                 <major>1</major>
@@ -790,9 +1004,278 @@ mod tests {
             </device>
         </root>"#;
 
-        let result = parse_services("http://xxxxxx:1337/", XML_ROOT)
+        let result = parse_services("http://xxxxxx:1337/", XML_ROOT, &StaticTransport(vec![]))
             .await
             .unwrap();
         assert_eq!(result.len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_parsing_services_through_transport() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0">
+            <device>
+                <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+                <serviceList>
+                    <service>
+                        <serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:RenderingControl</serviceId>
+                        <SCPDURL>/RenderingControl/scpd.xml</SCPDURL>
+                        <controlURL>/RenderingControl/control</controlURL>
+                        <eventSubURL>/RenderingControl/event</eventSubURL>
+                    </service>
+                </serviceList>
+            </device>
+        </root>"#;
+        const SCPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <scpd xmlns="urn:schemas-upnp-org:service-1-0">
+            <actionList>
+                <action>
+                    <name>GetVolume</name>
+                    <argumentList>
+                        <argument>
+                            <name>InstanceID</name>
+                            <direction>in</direction>
+                            <relatedStateVariable>A_ARG_TYPE_InstanceID</relatedStateVariable>
+                        </argument>
+                    </argumentList>
+                </action>
+            </actionList>
+            <serviceStateTable>
+                <stateVariable sendEvents="no">
+                    <name>Volume</name>
+                    <dataType>ui2</dataType>
+                    <allowedValueRange>
+                        <minimum>0</minimum>
+                        <maximum>100</maximum>
+                        <step>1</step>
+                    </allowedValueRange>
+                </stateVariable>
+                <stateVariable>
+                    <name>A_ARG_TYPE_Channel</name>
+                    <dataType>string</dataType>
+                    <allowedValueList>
+                        <allowedValue>Master</allowedValue>
+                    </allowedValueList>
+                </stateVariable>
+                <stateVariable sendEvents="no">
+                    <name>Brightness</name>
+                    <dataType>ui2</dataType>
+                    <allowedValueRange>
+                        <minimum>100</minimum>
+                        <maximum>0</maximum>
+                    </allowedValueRange>
+                </stateVariable>
+            </serviceStateTable>
+        </scpd>"#;
+
+        let transport =
+            StaticTransport(vec![("http://xxxxxx:1337/RenderingControl/scpd.xml", SCPD)]);
+        let result = parse_services("http://xxxxxx:1337/", XML_ROOT, &transport)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[0].control_url,
+            "http://xxxxxx:1337/RenderingControl/control"
+        );
+        assert_eq!(result[0].actions[0].name, "GetVolume");
+        assert_eq!(result[0].actions[0].arguments.len(), 1);
+
+        let volume = &result[0].state_variables[0];
+        assert_eq!(volume.name, "Volume");
+        assert!(!volume.send_events);
+        assert!(volume.allows("100"));
+        assert!(!volume.allows("101"));
+        let channel = &result[0].state_variables[1];
+        assert!(channel.send_events);
+        assert!(channel.allows("Master"));
+        assert!(!channel.allows("LF"));
+        let brightness = &result[0].state_variables[2];
+        assert_eq!(brightness.allowed_range, None);
+    }
+
+    #[test]
+    fn test_parsing_query_state_variable_response() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+        <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+            <s:Body>
+                <u:QueryStateVariableResponse xmlns:u="urn:schemas-upnp-org:control-1-0">
+                    <return>PLAYING</return>
+                </u:QueryStateVariableResponse>
+            </s:Body>
+        </s:Envelope>"#;
+
+        let result = parse_query_state_variable(XML_ROOT).unwrap();
+        assert_eq!(result, Some("PLAYING".to_string()));
+        assert_eq!(parse_upnp_error(XML_ROOT).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parsing_upnp_error() {
+        const XML_ROOT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+        <s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+            <s:Body>
+                <s:Fault>
+                    <faultcode>s:Client</faultcode>
+                    <faultstring>UPnPError</faultstring>
+                    <detail>
+                        <UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+                            <errorCode>401</errorCode>
+                            <errorDescription>Invalid Action</errorDescription>
+                        </UPnPError>
+                    </detail>
+                </s:Fault>
+            </s:Body>
+        </s:Envelope>"#;
+
+        let result = parse_upnp_error(XML_ROOT).unwrap();
+        assert_eq!(result, Some((401, "Invalid Action".to_string())));
+        assert_eq!(parse_query_state_variable(XML_ROOT).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parsing_subscription_timeout() {
+        assert_eq!(
+            parse_subscription_timeout("Second-1800").unwrap(),
+            Some(Duration::from_secs(1800))
+        );
+        assert_eq!(parse_subscription_timeout("Second-infinite").unwrap(), None);
+        assert!(parse_subscription_timeout("").is_err());
+        assert!(parse_subscription_timeout("Second-soon").is_err());
+    }
+
+    #[test]
+    fn test_parsing_property_set() {
+        let xml = r#"<?xml version="1.0"?>
+<e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">
+  <e:property><SourceProtocolInfo></SourceProtocolInfo></e:property>
+  <e:property><SinkProtocolInfo>http-get:*:audio/mpeg:*</SinkProtocolInfo></e:property>
+  <e:property><LastChange>&lt;Event xmlns=&quot;urn:schemas-upnp-org:metadata-1-0/AVT/&quot;/&gt;</LastChange></e:property>
+</e:propertyset>"#;
+        let values = parse_property_set(xml).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                ("SourceProtocolInfo".to_string(), "".to_string()),
+                (
+                    "SinkProtocolInfo".to_string(),
+                    "http-get:*:audio/mpeg:*".to_string()
+                ),
+                (
+                    "LastChange".to_string(),
+                    r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/"/>"#.to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parsing_last_change_values() {
+        let xml = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/RCS/">
+  <InstanceID val="0">
+    <Volume channel="Master" val="24"/>
+    <Mute channel="Master" val="0"/>
+  </InstanceID>
+  <InstanceID val="1">
+    <PresetNameList val="FactoryDefaults"/>
+  </InstanceID>
+</Event>"#;
+        let values = parse_last_change_values(xml).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                LastChangeValue {
+                    instance_id: 0,
+                    name: "Volume".to_string(),
+                    channel: Some("Master".to_string()),
+                    value: "24".to_string(),
+                },
+                LastChangeValue {
+                    instance_id: 0,
+                    name: "Mute".to_string(),
+                    channel: Some("Master".to_string()),
+                    value: "0".to_string(),
+                },
+                LastChangeValue {
+                    instance_id: 1,
+                    name: "PresetNameList".to_string(),
+                    channel: None,
+                    value: "FactoryDefaults".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parsing_time() {
+        assert_eq!(parse_time("0:03:25"), Some(Duration::from_secs(205)));
+        assert_eq!(parse_time("01:00:00"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_time("0:00:01.500"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_time("0:00:02.1/4"), Some(Duration::from_millis(2250)));
+        assert_eq!(parse_time("NOT_IMPLEMENTED"), None);
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("0:00:01.-1/2"), None);
+        assert_eq!(parse_time("0:00:01.1/-2"), None);
+        assert_eq!(parse_time("0:00:01.inf/1"), None);
+        assert_eq!(parse_time("0:00:01.NaN/1"), None);
+        assert_eq!(parse_time("0:00:01.1/1e-320"), None);
+        assert_eq!(parse_time("0:00:01.1e300/1e-300"), None);
+        assert_eq!(parse_time("18446744073709551615:00:00"), None);
+        assert_eq!(parse_time("0:18446744073709551615:00"), None);
+        assert_eq!(parse_time("0:00:18446744073709551615.1/1"), None);
+    }
+
+    #[test]
+    fn test_splitting_list() {
+        assert_eq!(split_list("Play, Stop,,Seek"), ["Play", "Stop", "Seek"]);
+        assert!(split_list("NOT_IMPLEMENTED").is_empty());
+        assert!(split_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parsing_speed() {
+        assert_eq!(parse_speed("1"), Some(1.0));
+        assert_eq!(parse_speed("-2"), Some(-2.0));
+        assert_eq!(parse_speed("1/2"), Some(0.5));
+        assert_eq!(parse_speed("1/0"), None);
+        assert_eq!(parse_speed("fast"), None);
+    }
+
+    #[test]
+    fn test_parsing_position_info() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:GetPositionInfoResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1">
+      <Track>2</Track>
+      <TrackDuration>0:03:25.500</TrackDuration>
+      <TrackMetaData>&lt;DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"&gt;&lt;item id="2"&gt;&lt;dc:title&gt;Song&lt;/dc:title&gt;&lt;upnp:artist&gt;Band&lt;/upnp:artist&gt;&lt;/item&gt;&lt;/DIDL-Lite&gt;</TrackMetaData>
+      <TrackURI>http://music/2.flac</TrackURI>
+      <RelTime>0:01:02.1/4</RelTime>
+      <AbsTime>NOT_IMPLEMENTED</AbsTime>
+      <RelCount>62</RelCount>
+      <AbsCount>2147483647</AbsCount>
+    </u:GetPositionInfoResponse>
+  </s:Body>
+</s:Envelope>"#;
+        assert_eq!(
+            parse_position_info(xml).unwrap(),
+            PositionInfo {
+                track: 2,
+                track_duration: Some(Duration::from_millis(205_500)),
+                track_metadata: Some(Metadata {
+                    url: "2".to_string(),
+                    title: "Song".to_string(),
+                    artist: Some("Band".to_string()),
+                    ..Default::default()
+                }),
+                track_uri: Some("http://music/2.flac".to_string()),
+                rel_time: Some(Duration::from_millis(62_250)),
+                abs_time: None,
+                rel_count: Some(62),
+                abs_count: None,
+            }
+        );
+    }
 }
@@ -0,0 +1,190 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    eventing::Subscription,
+    transport::{HttpRequest, HttpResponse, HttpTransport},
+    Error, Result,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Exchange {
+    Http {
+        request: HttpRequest,
+        response: HttpResponse,
+    },
+    Notify {
+        headers: Vec<(String, String)>,
+        body: String,
+    },
+}
+
+/// Every description fetch, SOAP call and NOTIFY seen by a `DeviceClient`, in order.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Archive {
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Archive {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn notifications(&self) -> Vec<(Vec<(String, String)>, String)> {
+        self.exchanges
+            .iter()
+            .filter_map(|exchange| match exchange {
+                Exchange::Notify { headers, body } => Some((headers.clone(), body.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Recorder {
+    archive: Arc<Mutex<Archive>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn archive(&self) -> Archive {
+        self.archive.lock().unwrap().clone()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.archive().save(path)
+    }
+
+    pub fn record(&self, exchange: Exchange) {
+        self.archive.lock().unwrap().exchanges.push(exchange);
+    }
+}
+
+pub struct RecordingTransport {
+    inner: Arc<dyn HttpTransport>,
+    recorder: Recorder,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for RecordingTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let response = self.inner.send(request.clone()).await?;
+        self.recorder.record(Exchange::Http {
+            request,
+            response: response.clone(),
+        });
+        Ok(response)
+    }
+}
+
+/// Serves the HTTP exchanges of an `Archive` back.
+///
+/// Requests are matched on method, URL and SOAPACTION, recorded responses are used
+/// in order and the last one is repeated once they run out.
+pub struct ReplayTransport {
+    exchanges: Vec<(HttpRequest, HttpResponse)>,
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayTransport {
+    pub fn new(archive: &Archive) -> Self {
+        let exchanges: Vec<(HttpRequest, HttpResponse)> = archive
+            .exchanges
+            .iter()
+            .filter_map(|exchange| match exchange {
+                Exchange::Http { request, response } => Some((request.clone(), response.clone())),
+                _ => None,
+            })
+            .collect();
+        let used = Mutex::new(vec![false; exchanges.len()]);
+        Self { exchanges, used }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(&Archive::load(path)?))
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReplayTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let mut used = self.used.lock().unwrap();
+        let candidates: Vec<usize> = self
+            .exchanges
+            .iter()
+            .enumerate()
+            .filter(|(_, (recorded, _))| matches(recorded, &request))
+            .map(|(index, _)| index)
+            .collect();
+        let index = candidates
+            .iter()
+            .find(|index| !used[**index])
+            .or_else(|| candidates.last())
+            .ok_or_else(|| {
+                Error::Transport(format!(
+                    "No recorded response for {} {}",
+                    request.method, request.url
+                ))
+            })?;
+        used[*index] = true;
+        Ok(self.exchanges[*index].1.clone())
+    }
+}
+
+/// Feeds the recorded NOTIFYs for the SID of `subscription` to it, in order, and
+/// tells how many there were. Together with `ReplayTransport`, which hands out the
+/// recorded SID, this replays an eventing session.
+pub fn replay_notifications(archive: &Archive, subscription: &Subscription) -> usize {
+    let sid = subscription.sid();
+    let notifications: Vec<_> = archive
+        .notifications()
+        .into_iter()
+        .filter(|(headers, _)| {
+            headers
+                .iter()
+                .any(|(name, value)| name.eq_ignore_ascii_case("SID") && value.trim() == sid)
+        })
+        .collect();
+    let count = notifications.len();
+    for (headers, body) in notifications {
+        subscription.deliver(headers, body);
+    }
+    count
+}
+
+fn matches(recorded: &HttpRequest, request: &HttpRequest) -> bool {
+    recorded.method.eq_ignore_ascii_case(&request.method)
+        && recorded.url == request.url
+        && header(recorded, "SOAPACTION") == header(request, "SOAPACTION")
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
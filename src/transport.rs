@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, Body, Client, Method};
+use serde::{Deserialize, Serialize};
+
+use crate::{types::ClientConfig, Error, Result};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    #[serde(skip)]
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: String) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// HTTP client used for description fetches, SOAP control and GENA subscriptions.
+///
+/// Implement this trait to plug in another HTTP stack or a fake device, and share
+/// one instance between clients through an `Arc`.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+/// The default transport, on hyper. It speaks plain HTTP only, plug in another
+/// `HttpTransport` for devices with https `LOCATION`s.
+#[derive(Clone)]
+pub struct HyperTransport {
+    client: Client<HttpConnector>,
+    read_timeout: Duration,
+}
+
+impl HyperTransport {
+    pub fn new(connect_timeout: Duration, read_timeout: Duration) -> Self {
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(connect_timeout));
+        Self {
+            client: Client::builder().build(connector),
+            read_timeout,
+        }
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> Self {
+        Self::from(&ClientConfig::default())
+    }
+}
+
+impl From<&ClientConfig> for HyperTransport {
+    fn from(config: &ClientConfig) -> Self {
+        Self::new(config.connect_timeout, config.read_timeout)
+    }
+}
+
+#[async_trait]
+impl HttpTransport for HyperTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        if request.url.starts_with("https:") {
+            return Err(Error::Transport(format!(
+                "{} needs an HttpTransport with TLS, HyperTransport only speaks http",
+                request.url
+            )));
+        }
+        let mut builder = hyper::Request::builder()
+            .method(Method::from_bytes(request.method.as_bytes())?)
+            .uri(request.url.as_str());
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let req = builder.body(Body::from(request.body))?;
+
+        let timeout = request.timeout.unwrap_or(self.read_timeout);
+        let res = tokio::time::timeout(timeout, async {
+            let res = self.client.request(req).await?;
+            let status = res.status().as_u16();
+            let headers = res
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).to_string(),
+                    )
+                })
+                .collect();
+            let body = hyper::body::to_bytes(res.into_body()).await?;
+            Ok::<_, hyper::Error>(HttpResponse {
+                status,
+                headers,
+                body: String::from_utf8_lossy(&body).to_string(),
+            })
+        })
+        .await
+        .map_err(|_| Error::Timeout(request.url.clone()))??;
+        Ok(res)
+    }
+}
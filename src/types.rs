@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
@@ -25,6 +25,8 @@ pub struct Service {
     pub event_sub_url: String,
     pub scpd_url: String,
     pub actions: Vec<Action>,
+    #[serde(default)]
+    pub state_variables: Vec<StateVariable>,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
@@ -40,6 +42,42 @@ pub struct Argument {
     pub related_state_variable: String,
 }
 
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct StateVariable {
+    pub name: String,
+    pub data_type: String,
+    pub send_events: bool,
+    pub default_value: Option<String>,
+    pub allowed_values: Vec<String>,
+    pub allowed_range: Option<AllowedRange>,
+}
+
+impl StateVariable {
+    /// Whether `value` is in the advertised value list and range, if any.
+    pub fn allows(&self, value: &str) -> bool {
+        if !self.allowed_values.is_empty() && !self.allowed_values.iter().any(|v| v == value) {
+            return false;
+        }
+        match (&self.allowed_range, value.parse::<i64>()) {
+            (Some(range), Ok(value)) => range.contains(value),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AllowedRange {
+    pub minimum: i64,
+    pub maximum: i64,
+    pub step: Option<i64>,
+}
+
+impl AllowedRange {
+    pub fn contains(&self, value: i64) -> bool {
+        (self.minimum..=self.maximum).contains(&value)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ObjectClass {
     Audio,
@@ -71,7 +109,7 @@ impl ObjectClass {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Metadata {
     pub url: String,
     pub title: String,
@@ -91,10 +129,167 @@ pub struct LoadOptions {
     pub autoplay: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    /// Retries for idempotent actions (Get*, List*, Browse, Search)
+    pub retries: u32,
+    /// Delay before the first retry, doubled on every following attempt
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Port of the event listener shared by all subscriptions, a free one when `None`
+    pub event_port: Option<u16>,
+    /// Host devices should send events to, instead of the local address that routes
+    /// to the device. Useful behind NAT or in containers.
+    pub callback_host: Option<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(5),
+            retries: 2,
+            backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(4),
+            event_port: None,
+            callback_host: None,
+        }
+    }
+}
+
+/// What a renderer is doing, as far as its events and polling tell.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RendererState {
+    pub transport_state: Option<TransportState>,
+    /// URI of the current track, not of the playlist or container it belongs to.
+    pub current_uri: Option<String>,
+    pub metadata: Option<Metadata>,
+    pub position: Option<Duration>,
+    /// Duration of the current track.
+    pub duration: Option<Duration>,
+    pub volume: Option<u8>,
+    pub mute: Option<bool>,
+    pub play_mode: Option<PlayMode>,
+}
+
+/// Playback progress of the current track, suitable for progress bars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub position: Duration,
+    pub duration: Option<Duration>,
+    /// `position` as a percentage of `duration`, when the duration is known.
+    pub percentage: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    pub timeout: Option<Duration>,
+    /// Overrides the configured retries, also for actions that are not idempotent
+    pub retries: Option<u32>,
+}
+
 #[derive(Debug)]
 pub enum AVTransportEvent {
+    TransportState {
+        sid: String,
+        instance_id: u32,
+        transport_state: TransportState,
+    },
+    TransportStatus {
+        sid: String,
+        instance_id: u32,
+        transport_status: TransportStatus,
+    },
+    PlaybackStorageMedium {
+        sid: String,
+        instance_id: u32,
+        medium: String,
+    },
+    RecordStorageMedium {
+        sid: String,
+        instance_id: u32,
+        medium: String,
+    },
+    PossiblePlaybackStorageMedia {
+        sid: String,
+        instance_id: u32,
+        media: Vec<String>,
+    },
+    PossibleRecordStorageMedia {
+        sid: String,
+        instance_id: u32,
+        media: Vec<String>,
+    },
+    CurrentPlayMode {
+        sid: String,
+        instance_id: u32,
+        play_mode: PlayMode,
+    },
+    TransportPlaySpeed {
+        sid: String,
+        instance_id: u32,
+        speed: String,
+    },
+    RecordMediumWriteStatus {
+        sid: String,
+        instance_id: u32,
+        status: String,
+    },
+    CurrentRecordQualityMode {
+        sid: String,
+        instance_id: u32,
+        mode: String,
+    },
+    PossibleRecordQualityModes {
+        sid: String,
+        instance_id: u32,
+        modes: Vec<String>,
+    },
+    NumberOfTracks {
+        sid: String,
+        instance_id: u32,
+        number_of_tracks: u32,
+    },
+    CurrentTrack {
+        sid: String,
+        instance_id: u32,
+        track: u32,
+    },
+    CurrentTrackDuration {
+        sid: String,
+        instance_id: u32,
+        duration: String,
+    },
+    CurrentMediaDuration {
+        sid: String,
+        instance_id: u32,
+        duration: String,
+    },
+    CurrentTrackURI {
+        sid: String,
+        instance_id: u32,
+        uri: String,
+    },
+    CurrentTrackMetadata {
+        sid: String,
+        instance_id: u32,
+        url: String,
+        title: String,
+        artist: Option<String>,
+        album: Option<String>,
+        album_art_uri: Option<String>,
+        genre: Option<String>,
+    },
+    AVTransportURI {
+        sid: String,
+        instance_id: u32,
+        uri: String,
+    },
     AVTransportURIMetaData {
         sid: String,
+        instance_id: u32,
         url: String,
         title: String,
         artist: Option<String>,
@@ -102,12 +297,14 @@ pub enum AVTransportEvent {
         album_art_uri: Option<String>,
         genre: Option<String>,
     },
-    CurrentPlayMode {
+    NextAVTransportURI {
         sid: String,
-        play_mode: String,
+        instance_id: u32,
+        uri: String,
     },
-    CurrentTrackMetadata {
+    NextAVTransportURIMetaData {
         sid: String,
+        instance_id: u32,
         url: String,
         title: String,
         artist: Option<String>,
@@ -115,59 +312,366 @@ pub enum AVTransportEvent {
         album_art_uri: Option<String>,
         genre: Option<String>,
     },
-    TransportState {
+    CurrentTransportActions {
+        sid: String,
+        instance_id: u32,
+        actions: Vec<String>,
+    },
+    RelativeTimePosition {
+        sid: String,
+        instance_id: u32,
+        position: String,
+    },
+    AbsoluteTimePosition {
+        sid: String,
+        instance_id: u32,
+        position: String,
+    },
+    RelativeCounterPosition {
+        sid: String,
+        instance_id: u32,
+        position: i32,
+    },
+    AbsoluteCounterPosition {
+        sid: String,
+        instance_id: u32,
+        position: i32,
+    },
+    /// A variable that is not part of the standard service
+    Other {
+        sid: String,
+        instance_id: u32,
+        name: String,
+        value: String,
+    },
+}
+
+/// One variable of a `LastChange` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastChangeValue {
+    pub instance_id: u32,
+    pub name: String,
+    /// Set for RenderingControl variables that are per channel
+    pub channel: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug)]
+pub enum RenderingControlEvent {
+    Volume {
+        sid: String,
+        instance_id: u32,
+        channel: String,
+        volume: u8,
+    },
+    VolumeDB {
+        sid: String,
+        instance_id: u32,
+        channel: String,
+        volume_db: i16,
+    },
+    Mute {
+        sid: String,
+        instance_id: u32,
+        channel: String,
+        mute: bool,
+    },
+    Loudness {
+        sid: String,
+        instance_id: u32,
+        channel: String,
+        loudness: bool,
+    },
+    PresetNameList {
+        sid: String,
+        instance_id: u32,
+        presets: Vec<String>,
+    },
+    /// Any other variable, such as the picture controls
+    Other {
         sid: String,
-        transport_state: String,
+        instance_id: u32,
+        name: String,
+        channel: Option<String>,
+        value: String,
     },
 }
 
 #[derive(Debug)]
 pub enum Event {
     AVTransport(AVTransportEvent),
+    RenderingControl(RenderingControlEvent),
+    /// Every variable of a NOTIFY, sent for any service before its typed events.
+    StateVariables {
+        sid: String,
+        service_id: String,
+        values: Vec<(String, String)>,
+    },
+    /// The device forgot the subscription, events now arrive under a new SID.
+    Resubscribed {
+        previous_sid: String,
+        sid: String,
+    },
+    /// Events were missed, the subscription was made again to get the full state.
+    Resynced {
+        previous_sid: String,
+        sid: String,
+    },
+    /// A NOTIFY for the subscription could not be parsed.
+    InvalidNotification {
+        sid: String,
+        error: String,
+    },
+    /// The subscription could not be renewed, renewal is retried on a short backoff
+    /// before the subscription expires.
+    RenewalFailed {
+        sid: String,
+        error: String,
+    },
 }
 
 impl Display for Event {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Event::AVTransport(event) => match event {
-                AVTransportEvent::AVTransportURIMetaData {
+                AVTransportEvent::TransportState { sid, instance_id, transport_state } => write!(
+                    f,
+                    "AVTransportEvent::TransportState {{\n sid: {},\n instance_id: {},\n transport_state: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), transport_state.bright_green()
+                ),
+                AVTransportEvent::TransportStatus { sid, instance_id, transport_status } => write!(
+                    f,
+                    "AVTransportEvent::TransportStatus {{\n sid: {},\n instance_id: {},\n transport_status: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), transport_status.bright_green()
+                ),
+                AVTransportEvent::PlaybackStorageMedium { sid, instance_id, medium } => write!(
+                    f,
+                    "AVTransportEvent::PlaybackStorageMedium {{\n sid: {},\n instance_id: {},\n medium: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), medium.bright_green()
+                ),
+                AVTransportEvent::RecordStorageMedium { sid, instance_id, medium } => write!(
+                    f,
+                    "AVTransportEvent::RecordStorageMedium {{\n sid: {},\n instance_id: {},\n medium: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), medium.bright_green()
+                ),
+                AVTransportEvent::PossiblePlaybackStorageMedia { sid, instance_id, media } => write!(
+                    f,
+                    "AVTransportEvent::PossiblePlaybackStorageMedia {{\n sid: {},\n instance_id: {},\n media: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), media.bright_green()
+                ),
+                AVTransportEvent::PossibleRecordStorageMedia { sid, instance_id, media } => write!(
+                    f,
+                    "AVTransportEvent::PossibleRecordStorageMedia {{\n sid: {},\n instance_id: {},\n media: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), media.bright_green()
+                ),
+                AVTransportEvent::CurrentPlayMode { sid, instance_id, play_mode } => write!(
+                    f,
+                    "AVTransportEvent::CurrentPlayMode {{\n sid: {},\n instance_id: {},\n play_mode: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), play_mode.bright_green()
+                ),
+                AVTransportEvent::TransportPlaySpeed { sid, instance_id, speed } => write!(
+                    f,
+                    "AVTransportEvent::TransportPlaySpeed {{\n sid: {},\n instance_id: {},\n speed: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), speed.bright_green()
+                ),
+                AVTransportEvent::RecordMediumWriteStatus { sid, instance_id, status } => write!(
+                    f,
+                    "AVTransportEvent::RecordMediumWriteStatus {{\n sid: {},\n instance_id: {},\n status: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), status.bright_green()
+                ),
+                AVTransportEvent::CurrentRecordQualityMode { sid, instance_id, mode } => write!(
+                    f,
+                    "AVTransportEvent::CurrentRecordQualityMode {{\n sid: {},\n instance_id: {},\n mode: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), mode.bright_green()
+                ),
+                AVTransportEvent::PossibleRecordQualityModes { sid, instance_id, modes } => write!(
+                    f,
+                    "AVTransportEvent::PossibleRecordQualityModes {{\n sid: {},\n instance_id: {},\n modes: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), modes.bright_green()
+                ),
+                AVTransportEvent::NumberOfTracks { sid, instance_id, number_of_tracks } => write!(
+                    f,
+                    "AVTransportEvent::NumberOfTracks {{\n sid: {},\n instance_id: {},\n number_of_tracks: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), number_of_tracks.bright_green()
+                ),
+                AVTransportEvent::CurrentTrack { sid, instance_id, track } => write!(
+                    f,
+                    "AVTransportEvent::CurrentTrack {{\n sid: {},\n instance_id: {},\n track: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), track.bright_green()
+                ),
+                AVTransportEvent::CurrentTrackDuration { sid, instance_id, duration } => write!(
+                    f,
+                    "AVTransportEvent::CurrentTrackDuration {{\n sid: {},\n instance_id: {},\n duration: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), duration.bright_green()
+                ),
+                AVTransportEvent::CurrentMediaDuration { sid, instance_id, duration } => write!(
+                    f,
+                    "AVTransportEvent::CurrentMediaDuration {{\n sid: {},\n instance_id: {},\n duration: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), duration.bright_green()
+                ),
+                AVTransportEvent::CurrentTrackURI { sid, instance_id, uri } => write!(
+                    f,
+                    "AVTransportEvent::CurrentTrackURI {{\n sid: {},\n instance_id: {},\n uri: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), uri.bright_green()
+                ),
+                AVTransportEvent::CurrentTrackMetadata { sid, instance_id, url, title, artist, album, album_art_uri, genre } => write!(
+                    f,
+                    "AVTransportEvent::CurrentTrackMetadata {{\n sid: {},\n instance_id: {},\n url: {},\n title: {},\n artist: {:?},\n album: {:?},\n album_art_uri: {:?},\n genre: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), url.bright_green(), title.bright_green(), artist.bright_green(), album.bright_green(), album_art_uri.bright_green(), genre.bright_green()
+                ),
+                AVTransportEvent::AVTransportURI { sid, instance_id, uri } => write!(
+                    f,
+                    "AVTransportEvent::AVTransportURI {{\n sid: {},\n instance_id: {},\n uri: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), uri.bright_green()
+                ),
+                AVTransportEvent::AVTransportURIMetaData { sid, instance_id, url, title, artist, album, album_art_uri, genre } => write!(
+                    f,
+                    "AVTransportEvent::AVTransportURIMetaData {{\n sid: {},\n instance_id: {},\n url: {},\n title: {},\n artist: {:?},\n album: {:?},\n album_art_uri: {:?},\n genre: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), url.bright_green(), title.bright_green(), artist.bright_green(), album.bright_green(), album_art_uri.bright_green(), genre.bright_green()
+                ),
+                AVTransportEvent::NextAVTransportURI { sid, instance_id, uri } => write!(
+                    f,
+                    "AVTransportEvent::NextAVTransportURI {{\n sid: {},\n instance_id: {},\n uri: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), uri.bright_green()
+                ),
+                AVTransportEvent::NextAVTransportURIMetaData { sid, instance_id, url, title, artist, album, album_art_uri, genre } => write!(
+                    f,
+                    "AVTransportEvent::NextAVTransportURIMetaData {{\n sid: {},\n instance_id: {},\n url: {},\n title: {},\n artist: {:?},\n album: {:?},\n album_art_uri: {:?},\n genre: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), url.bright_green(), title.bright_green(), artist.bright_green(), album.bright_green(), album_art_uri.bright_green(), genre.bright_green()
+                ),
+                AVTransportEvent::CurrentTransportActions { sid, instance_id, actions } => write!(
+                    f,
+                    "AVTransportEvent::CurrentTransportActions {{\n sid: {},\n instance_id: {},\n actions: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), actions.bright_green()
+                ),
+                AVTransportEvent::RelativeTimePosition { sid, instance_id, position } => write!(
+                    f,
+                    "AVTransportEvent::RelativeTimePosition {{\n sid: {},\n instance_id: {},\n position: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), position.bright_green()
+                ),
+                AVTransportEvent::AbsoluteTimePosition { sid, instance_id, position } => write!(
+                    f,
+                    "AVTransportEvent::AbsoluteTimePosition {{\n sid: {},\n instance_id: {},\n position: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), position.bright_green()
+                ),
+                AVTransportEvent::RelativeCounterPosition { sid, instance_id, position } => write!(
+                    f,
+                    "AVTransportEvent::RelativeCounterPosition {{\n sid: {},\n instance_id: {},\n position: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), position.bright_green()
+                ),
+                AVTransportEvent::AbsoluteCounterPosition { sid, instance_id, position } => write!(
+                    f,
+                    "AVTransportEvent::AbsoluteCounterPosition {{\n sid: {},\n instance_id: {},\n position: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), position.bright_green()
+                ),
+                AVTransportEvent::Other { sid, instance_id, name, value } => write!(
+                    f,
+                    "AVTransportEvent::Other {{\n sid: {},\n instance_id: {},\n name: {},\n value: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), name.bright_green(), value.bright_green()
+                ),
+            },
+            Event::RenderingControl(event) => match event {
+                RenderingControlEvent::Volume {
                     sid,
-                    url,
-                    title,
-                    artist,
-                    album,
-                    album_art_uri,
-                    genre,
+                    instance_id,
+                    channel,
+                    volume,
                 } => write!(
                     f,
-                    "AVTransportEvent::AVTransportURIMetaData {{\n sid: {},\n url: {},\n title: {},\n artist: {:?},\n album: {:?},\n album_art_uri: {:?},\n genre: {:?}\n }}",
-                    sid.bright_green(), url.bright_green(), title.bright_green(), artist.bright_green(), album.bright_green(), album_art_uri.bright_green(), genre.bright_green()
+                    "RenderingControlEvent::Volume {{\n sid: {},\n instance_id: {},\n channel: {},\n volume: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), channel.bright_green(), volume.bright_green()
                 ),
-                AVTransportEvent::CurrentPlayMode { sid, play_mode } => {
-                    write!(f, "AVTransportEvent::CurrentPlayMode {{\n sid: {}, play_mode: {}\n }}", sid.bright_green(), play_mode.bright_green())
-                }
-                AVTransportEvent::CurrentTrackMetadata {
+                RenderingControlEvent::VolumeDB {
                     sid,
-                    url,
-                    title,
-                    artist,
-                    album,
-                    album_art_uri,
-                    genre,
+                    instance_id,
+                    channel,
+                    volume_db,
                 } => write!(
                     f,
-                    "AVTransportEvent::CurrentTrackMetadata {{\n sid: {},\n url: {},\n title: {},\n artist: {:?},\n album: {:?},\n album_art_uri: {:?},\n genre: {:?}\n }}",
-                    sid.bright_green(), url.bright_green(), title.bright_green(), artist.bright_green(), album.bright_green(), album_art_uri.bright_green(), genre.bright_green()
+                    "RenderingControlEvent::VolumeDB {{\n sid: {},\n instance_id: {},\n channel: {},\n volume_db: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), channel.bright_green(), volume_db.bright_green()
                 ),
-                AVTransportEvent::TransportState {
+                RenderingControlEvent::Mute {
                     sid,
-                    transport_state,
+                    instance_id,
+                    channel,
+                    mute,
                 } => write!(
                     f,
-                    "AVTransportEvent::TransportState {{\n sid: {},\n transport_state: {}\n }}",
-                    sid.bright_green(), transport_state.bright_green()
+                    "RenderingControlEvent::Mute {{\n sid: {},\n instance_id: {},\n channel: {},\n mute: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), channel.bright_green(), mute.bright_green()
+                ),
+                RenderingControlEvent::Loudness {
+                    sid,
+                    instance_id,
+                    channel,
+                    loudness,
+                } => write!(
+                    f,
+                    "RenderingControlEvent::Loudness {{\n sid: {},\n instance_id: {},\n channel: {},\n loudness: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), channel.bright_green(), loudness.bright_green()
+                ),
+                RenderingControlEvent::PresetNameList {
+                    sid,
+                    instance_id,
+                    presets,
+                } => write!(
+                    f,
+                    "RenderingControlEvent::PresetNameList {{\n sid: {},\n instance_id: {},\n presets: {:?}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), presets.bright_green()
+                ),
+                RenderingControlEvent::Other {
+                    sid,
+                    instance_id,
+                    name,
+                    channel,
+                    value,
+                } => write!(
+                    f,
+                    "RenderingControlEvent::Other {{\n sid: {},\n instance_id: {},\n name: {},\n channel: {:?},\n value: {}\n }}",
+                    sid.bright_green(), instance_id.bright_green(), name.bright_green(), channel.bright_green(), value.bright_green()
                 ),
             },
+            Event::StateVariables {
+                sid,
+                service_id,
+                values,
+            } => {
+                write!(
+                    f,
+                    "Event::StateVariables {{\n sid: {},\n service_id: {},\n values: {{",
+                    sid.bright_green(),
+                    service_id.bright_green()
+                )?;
+                for (name, value) in values {
+                    write!(f, "\n  {}: {}", name, value.bright_green())?;
+                }
+                write!(f, "\n }}\n }}")
+            }
+            Event::Resubscribed { previous_sid, sid } => write!(
+                f,
+                "Event::Resubscribed {{\n previous_sid: {},\n sid: {}\n }}",
+                previous_sid.bright_green(),
+                sid.bright_green()
+            ),
+            Event::Resynced { previous_sid, sid } => write!(
+                f,
+                "Event::Resynced {{\n previous_sid: {},\n sid: {}\n }}",
+                previous_sid.bright_green(),
+                sid.bright_green()
+            ),
+            Event::InvalidNotification { sid, error } => write!(
+                f,
+                "Event::InvalidNotification {{\n sid: {},\n error: {}\n }}",
+                sid.bright_green(),
+                error.bright_red()
+            ),
+            Event::RenewalFailed { sid, error } => write!(
+                f,
+                "Event::RenewalFailed {{\n sid: {},\n error: {}\n }}",
+                sid.bright_green(),
+                error.bright_red()
+            ),
         }
     }
 }
@@ -213,9 +717,266 @@ pub struct Item {
     pub object_class: Option<ObjectClass>,
 }
 
+/// Video settings of a RenderingControl service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PictureControl {
+    Brightness,
+    Contrast,
+    Sharpness,
+    ColorTemperature,
+    HorizontalKeystone,
+    VerticalKeystone,
+}
+
+impl PictureControl {
+    /// Name of the state variable, and of the Get/Set actions without their prefix.
+    pub fn value(&self) -> &'static str {
+        match self {
+            PictureControl::Brightness => "Brightness",
+            PictureControl::Contrast => "Contrast",
+            PictureControl::Sharpness => "Sharpness",
+            PictureControl::ColorTemperature => "ColorTemperature",
+            PictureControl::HorizontalKeystone => "HorizontalKeystone",
+            PictureControl::VerticalKeystone => "VerticalKeystone",
+        }
+    }
+}
+
+/// Where to seek to, in one of the AVTransport seek units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeekTarget {
+    /// Time from the start of the media
+    AbsTime(Duration),
+    /// Time from the start of the current track
+    RelTime(Duration),
+    TrackNr(u32),
+    AbsCount(i32),
+    RelCount(i32),
+    /// Byte offset in the current track, `X_DLNA_REL_BYTE`
+    RelByte(u64),
+    Frame(u64),
+    RelFrame(u64),
+}
+
+impl SeekTarget {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            SeekTarget::AbsTime(_) => "ABS_TIME",
+            SeekTarget::RelTime(_) => "REL_TIME",
+            SeekTarget::TrackNr(_) => "TRACK_NR",
+            SeekTarget::AbsCount(_) => "ABS_COUNT",
+            SeekTarget::RelCount(_) => "REL_COUNT",
+            SeekTarget::RelByte(_) => "X_DLNA_REL_BYTE",
+            SeekTarget::Frame(_) => "FRAME",
+            SeekTarget::RelFrame(_) => "REL_FRAME",
+        }
+    }
+
+    pub fn target(&self) -> String {
+        match self {
+            SeekTarget::AbsTime(time) | SeekTarget::RelTime(time) => format_time(*time),
+            SeekTarget::TrackNr(track) => track.to_string(),
+            SeekTarget::AbsCount(count) | SeekTarget::RelCount(count) => count.to_string(),
+            SeekTarget::RelByte(value) | SeekTarget::Frame(value) | SeekTarget::RelFrame(value) => {
+                value.to_string()
+            }
+        }
+    }
+}
+
+/// `H+:MM:SS`, with milliseconds when there are any.
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    let formatted = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    );
+    match time.subsec_millis() {
+        0 => formatted,
+        millis => format!("{}.{:03}", formatted, millis),
+    }
+}
+
+/// Answer to GetPositionInfo, values the renderer does not implement are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PositionInfo {
+    pub track: u32,
+    pub track_duration: Option<Duration>,
+    pub track_metadata: Option<Metadata>,
+    pub track_uri: Option<String>,
+    pub rel_time: Option<Duration>,
+    pub abs_time: Option<Duration>,
+    pub rel_count: Option<i32>,
+    pub abs_count: Option<i32>,
+}
+
+/// Answer to GetMediaInfo, values the renderer does not implement are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaInfo {
+    pub nr_tracks: u32,
+    pub media_duration: Option<Duration>,
+    pub current_uri: Option<String>,
+    pub current_uri_metadata: Option<Metadata>,
+    pub next_uri: Option<String>,
+    pub next_uri_metadata: Option<Metadata>,
+    pub play_medium: Option<String>,
+    pub record_medium: Option<String>,
+    pub write_status: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportSettings {
+    pub play_mode: PlayMode,
+    pub rec_quality_mode: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    pub play_media: Vec<String>,
+    pub rec_media: Vec<String>,
+    pub rec_quality_modes: Vec<String>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct TransportInfo {
-    pub current_transport_state: String,
-    pub current_transport_status: String,
+    pub current_transport_state: TransportState,
+    pub current_transport_status: TransportStatus,
     pub current_speed: String,
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum TransportState {
+    #[default]
+    Stopped,
+    Playing,
+    Transitioning,
+    PausedPlayback,
+    PausedRecording,
+    Recording,
+    NoMediaPresent,
+    /// Vendor-specific state, e.g. `X_DLNA_...`
+    Other(String),
+}
+
+impl From<&str> for TransportState {
+    fn from(value: &str) -> Self {
+        match value {
+            "STOPPED" => TransportState::Stopped,
+            "PLAYING" => TransportState::Playing,
+            "TRANSITIONING" => TransportState::Transitioning,
+            "PAUSED_PLAYBACK" => TransportState::PausedPlayback,
+            "PAUSED_RECORDING" => TransportState::PausedRecording,
+            "RECORDING" => TransportState::Recording,
+            "NO_MEDIA_PRESENT" => TransportState::NoMediaPresent,
+            value => TransportState::Other(value.to_string()),
+        }
+    }
+}
+
+impl TransportState {
+    pub fn value(&self) -> &str {
+        match self {
+            TransportState::Stopped => "STOPPED",
+            TransportState::Playing => "PLAYING",
+            TransportState::Transitioning => "TRANSITIONING",
+            TransportState::PausedPlayback => "PAUSED_PLAYBACK",
+            TransportState::PausedRecording => "PAUSED_RECORDING",
+            TransportState::Recording => "RECORDING",
+            TransportState::NoMediaPresent => "NO_MEDIA_PRESENT",
+            TransportState::Other(value) => value,
+        }
+    }
+}
+
+impl Display for TransportState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.value())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum TransportStatus {
+    #[default]
+    Ok,
+    ErrorOccurred,
+    /// Vendor-specific status
+    Other(String),
+}
+
+impl From<&str> for TransportStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "OK" => TransportStatus::Ok,
+            "ERROR_OCCURRED" => TransportStatus::ErrorOccurred,
+            value => TransportStatus::Other(value.to_string()),
+        }
+    }
+}
+
+impl TransportStatus {
+    pub fn value(&self) -> &str {
+        match self {
+            TransportStatus::Ok => "OK",
+            TransportStatus::ErrorOccurred => "ERROR_OCCURRED",
+            TransportStatus::Other(value) => value,
+        }
+    }
+}
+
+impl Display for TransportStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.value())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum PlayMode {
+    #[default]
+    Normal,
+    Shuffle,
+    RepeatOne,
+    RepeatAll,
+    Random,
+    Direct1,
+    Intro,
+    /// Vendor-specific play mode
+    Other(String),
+}
+
+impl From<&str> for PlayMode {
+    fn from(value: &str) -> Self {
+        match value {
+            "NORMAL" => PlayMode::Normal,
+            "SHUFFLE" => PlayMode::Shuffle,
+            "REPEAT_ONE" => PlayMode::RepeatOne,
+            "REPEAT_ALL" => PlayMode::RepeatAll,
+            "RANDOM" => PlayMode::Random,
+            "DIRECT_1" => PlayMode::Direct1,
+            "INTRO" => PlayMode::Intro,
+            value => PlayMode::Other(value.to_string()),
+        }
+    }
+}
+
+impl PlayMode {
+    pub fn value(&self) -> &str {
+        match self {
+            PlayMode::Normal => "NORMAL",
+            PlayMode::Shuffle => "SHUFFLE",
+            PlayMode::RepeatOne => "REPEAT_ONE",
+            PlayMode::RepeatAll => "REPEAT_ALL",
+            PlayMode::Random => "RANDOM",
+            PlayMode::Direct1 => "DIRECT_1",
+            PlayMode::Intro => "INTRO",
+            PlayMode::Other(value) => value,
+        }
+    }
+}
+
+impl Display for PlayMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.value())
+    }
+}
@@ -0,0 +1,308 @@
+mod support;
+
+use std::{collections::HashMap, time::Duration};
+
+use support::{upnp_error, FakeDevice};
+use upnp_client::{
+    device_client::DeviceClient,
+    transport::{HttpRequest, HttpTransport, HyperTransport},
+    types::{CallOptions, ClientConfig},
+    Error,
+};
+
+#[tokio::test]
+async fn test_connect_parses_services_and_actions() {
+    let device = FakeDevice::start("media_renderer").await;
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    assert_eq!(client.ip(), "127.0.0.1");
+    let paths: Vec<String> = device.requests().into_iter().map(|r| r.path).collect();
+    assert!(paths.contains(&"/description.xml".to_string()));
+    assert!(paths.contains(&"/AVTransport/scpd.xml".to_string()));
+    assert!(paths.contains(&"/RenderingControl/scpd.xml".to_string()));
+    assert!(paths.contains(&"/ConnectionManager/scpd.xml".to_string()));
+}
+
+#[tokio::test]
+async fn test_call_action_sends_soap_request() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "42")]);
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let mut params = HashMap::new();
+    params.insert("InstanceID".to_string(), "0".to_string());
+    params.insert("Channel".to_string(), "Master".to_string());
+    let response = client
+        .call_action("RenderingControl", "GetVolume", params)
+        .await
+        .unwrap();
+    assert!(response.contains("<CurrentVolume>42</CurrentVolume>"));
+
+    let requests = device.action_requests("GetVolume");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/RenderingControl/control");
+    assert_eq!(
+        requests[0].header("soapaction"),
+        Some("\"urn:schemas-upnp-org:service:RenderingControl:1#GetVolume\"")
+    );
+    assert_eq!(requests[0].argument("Channel"), Some("Master".to_string()));
+}
+
+#[tokio::test]
+async fn test_call_action_rejects_unknown_action() {
+    let device = FakeDevice::start("media_renderer").await;
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let result = client
+        .call_action("AVTransport", "Record", HashMap::new())
+        .await;
+    assert!(matches!(result, Err(Error::ActionNotFound(action)) if action == "Record"));
+    assert!(device.action_requests("Record").is_empty());
+}
+
+#[tokio::test]
+async fn test_call_action_reports_soap_fault() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond_raw("Seek", 500, &upnp_error(710, "Seek mode not supported"));
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let mut params = HashMap::new();
+    params.insert("InstanceID".to_string(), "0".to_string());
+    params.insert("Unit".to_string(), "REL_TIME".to_string());
+    params.insert("Target".to_string(), "00:00:10".to_string());
+    let result = client.call_action("AVTransport", "Seek", params).await;
+    assert!(matches!(
+        result,
+        Err(Error::SoapFault { code: 710, description }) if description == "Seek mode not supported"
+    ));
+}
+
+#[tokio::test]
+async fn test_unreachable_device() {
+    let result = DeviceClient::new("http://127.0.0.1:1/description.xml")
+        .unwrap()
+        .connect()
+        .await;
+    assert!(matches!(result, Err(Error::Transport(_))));
+
+    let client = DeviceClient::new("http://127.0.0.1:1/description.xml").unwrap();
+    let result = client
+        .call_action("AVTransport", "Play", HashMap::new())
+        .await;
+    assert!(matches!(result, Err(Error::DeviceNotConnected)));
+}
+
+#[tokio::test]
+async fn test_invalid_request_is_not_a_transport_error() {
+    let request = HttpRequest::new("NOT A METHOD", "http://127.0.0.1:1/");
+    let result = HyperTransport::default().send(request).await;
+    assert!(matches!(result, Err(Error::Request(_))));
+}
+
+#[tokio::test]
+async fn test_https_needs_another_transport() {
+    let result = DeviceClient::new("https://127.0.0.1:1/description.xml")
+        .unwrap()
+        .connect()
+        .await;
+    assert!(matches!(result, Err(Error::Transport(message)) if message.contains("TLS")));
+}
+
+#[tokio::test]
+async fn test_query_state_variable() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("QueryStateVariable", &[("return", "PLAYING")]);
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let value = client
+        .query_state_variable("AVTransport", "TransportState")
+        .await
+        .unwrap();
+    assert_eq!(value, Some("PLAYING".to_string()));
+
+    let requests = device.action_requests("QueryStateVariable");
+    assert_eq!(
+        requests[0].header("soapaction"),
+        Some("\"urn:schemas-upnp-org:control-1-0#QueryStateVariable\"")
+    );
+    assert_eq!(
+        requests[0].argument("u:varName"),
+        Some("TransportState".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_query_state_variable_unsupported() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond_raw(
+        "QueryStateVariable",
+        500,
+        &upnp_error(401, "Invalid Action"),
+    );
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let value = client
+        .query_state_variable("AVTransport", "TransportState")
+        .await
+        .unwrap();
+    assert_eq!(value, None);
+
+    device.respond_raw("QueryStateVariable", 404, "<html>Not Found</html>");
+    let result = client
+        .query_state_variable("AVTransport", "TransportState")
+        .await;
+    assert!(matches!(result, Err(Error::HttpStatus { status: 404, .. })));
+}
+
+#[tokio::test]
+async fn test_subscribe_sends_gena_request() {
+    let device = FakeDevice::start("media_renderer").await;
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let _subscription = client.subscribe("AVTransport").await.unwrap();
+
+    let subscribe = device
+        .requests()
+        .into_iter()
+        .find(|r| r.method == "SUBSCRIBE")
+        .unwrap();
+    assert_eq!(subscribe.path, "/AVTransport/event");
+    assert_eq!(subscribe.header("nt"), Some("upnp:event"));
+    assert!(subscribe
+        .header("callback")
+        .unwrap()
+        .starts_with("<http://127.0.0.1:"));
+    assert_eq!(device.subscriptions().len(), 1);
+}
+
+#[tokio::test]
+async fn test_callback_host_override() {
+    let device = FakeDevice::start("media_renderer").await;
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .with_config(ClientConfig {
+            callback_host: Some("192.0.2.10".to_string()),
+            ..Default::default()
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    let _subscription = client.subscribe("AVTransport").await.unwrap();
+
+    let callback = &device.subscriptions()[0].callback;
+    assert!(callback.starts_with("http://192.0.2.10:"));
+}
+
+#[tokio::test]
+async fn test_ipv6_callback_host_is_bracketed() {
+    let device = FakeDevice::start("media_renderer").await;
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .with_config(ClientConfig {
+            callback_host: Some("2001:db8::10".to_string()),
+            ..Default::default()
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    let _subscription = client.subscribe("AVTransport").await.unwrap();
+
+    let callback = &device.subscriptions()[0].callback;
+    assert!(callback.starts_with("http://[2001:db8::10]:"));
+    url::Url::parse(callback).unwrap();
+}
+
+#[tokio::test]
+async fn test_idempotent_actions_are_retried() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.delay("GetVolume", Duration::from_millis(500));
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .with_config(ClientConfig {
+            read_timeout: Duration::from_millis(100),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    let mut params = HashMap::new();
+    params.insert("InstanceID".to_string(), "0".to_string());
+    params.insert("Channel".to_string(), "Master".to_string());
+    let result = client
+        .call_action("RenderingControl", "GetVolume", params.clone())
+        .await;
+    assert!(matches!(result, Err(Error::Timeout(_))));
+    assert_eq!(device.action_requests("GetVolume").len(), 3);
+
+    params.insert("DesiredVolume".to_string(), "10".to_string());
+    device.delay("SetVolume", Duration::from_millis(500));
+    let result = client
+        .call_action("RenderingControl", "SetVolume", params)
+        .await;
+    assert!(result.is_err());
+    assert_eq!(device.action_requests("SetVolume").len(), 1);
+}
+
+#[tokio::test]
+async fn test_call_options_override_config() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.delay("GetVolume", Duration::from_millis(200));
+    device.respond("GetVolume", &[("CurrentVolume", "5")]);
+    let client = DeviceClient::new(&device.location())
+        .unwrap()
+        .with_config(ClientConfig {
+            read_timeout: Duration::from_millis(50),
+            retries: 0,
+            ..Default::default()
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    let mut params = HashMap::new();
+    params.insert("InstanceID".to_string(), "0".to_string());
+    params.insert("Channel".to_string(), "Master".to_string());
+    let options = CallOptions {
+        timeout: Some(Duration::from_secs(2)),
+        ..Default::default()
+    };
+    let response = client
+        .call_action_with_options("RenderingControl", "GetVolume", params, options)
+        .await
+        .unwrap();
+    assert!(response.contains("<CurrentVolume>5</CurrentVolume>"));
+    assert_eq!(device.action_requests("GetVolume").len(), 1);
+}
@@ -0,0 +1,139 @@
+mod support;
+
+use std::{
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use support::{last_change, property_set, FakeDevice};
+use upnp_client::{device_client::DeviceClient, types::ClientConfig};
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+async fn connect(device: &FakeDevice, event_port: u16) -> DeviceClient {
+    DeviceClient::new(&device.location())
+        .unwrap()
+        .with_config(ClientConfig {
+            event_port: Some(event_port),
+            ..Default::default()
+        })
+        .connect()
+        .await
+        .unwrap()
+}
+
+fn callback_port(device: &FakeDevice) -> u16 {
+    let callback = &device.subscriptions()[0].callback;
+    url::Url::parse(callback).unwrap().port().unwrap()
+}
+
+#[tokio::test]
+async fn test_subscriptions_share_one_listener() {
+    let port = free_port();
+    let living_room = FakeDevice::start("media_renderer").await;
+    let kitchen = FakeDevice::start("media_renderer").await;
+
+    let living_room_client = connect(&living_room, port).await;
+    let av_transport = living_room_client.subscribe("AVTransport").await.unwrap();
+    let rendering_control = living_room_client
+        .subscribe("RenderingControl")
+        .await
+        .unwrap();
+    let kitchen_events = connect(&kitchen, port)
+        .await
+        .subscribe("AVTransport")
+        .await
+        .unwrap();
+
+    assert_eq!(callback_port(&living_room), port);
+    assert_eq!(callback_port(&kitchen), port);
+    let callbacks: Vec<String> = living_room
+        .subscriptions()
+        .into_iter()
+        .map(|subscription| subscription.callback)
+        .collect();
+    assert_ne!(callbacks[0], callbacks[1]);
+
+    let playing = property_set(&[("LastChange", &last_change(&[("TransportState", "PLAYING")]))]);
+    assert_eq!(kitchen.notify("AVTransport", 0, &playing).await, 200);
+
+    drop(av_transport);
+    drop(rendering_control);
+    assert_eq!(kitchen.notify("AVTransport", 1, &playing).await, 200);
+
+    drop(kitchen_events);
+    let _restarted = living_room_client.subscribe("AVTransport").await.unwrap();
+    assert!(TcpStream::connect(("127.0.0.1", port)).is_ok());
+}
+
+#[tokio::test]
+async fn test_listener_port_is_reused_right_away() {
+    let port = free_port();
+    let device = FakeDevice::start("media_renderer").await;
+    let client = connect(&device, port).await;
+
+    for _ in 0..5 {
+        let subscription = client.subscribe("AVTransport").await.unwrap();
+        drop(subscription);
+    }
+    let _subscription = client.subscribe("AVTransport").await.unwrap();
+    assert!(TcpStream::connect(("127.0.0.1", port)).is_ok());
+}
+
+#[tokio::test]
+async fn test_cancelled_subscribe_does_not_hold_events_back() {
+    let port = free_port();
+    let device = FakeDevice::start("media_renderer").await;
+    let client = connect(&device, port).await;
+    let _subscription = client.subscribe("RenderingControl").await.unwrap();
+    let gena = device.subscriptions()[0].clone();
+
+    device.delay("SUBSCRIBE", Duration::from_millis(500));
+    let cancelled =
+        tokio::time::timeout(Duration::from_millis(100), client.subscribe("AVTransport")).await;
+    assert!(cancelled.is_err());
+
+    let body = property_set(&[("LastChange", &last_change(&[("Volume", "10")]))]);
+    assert_eq!(
+        device
+            .notify_with_sid(&gena, "uuid:unknown", 0, &body)
+            .await,
+        412
+    );
+}
+
+#[tokio::test]
+async fn test_aborted_resubscribe_does_not_hold_events_back() {
+    let port = free_port();
+    let device = FakeDevice::start("media_renderer").await;
+    let client = connect(&device, port).await;
+    let _rendering_control = client.subscribe("RenderingControl").await.unwrap();
+    let av_transport = client.subscribe("AVTransport").await.unwrap();
+    let gena = device.subscriptions()[0].clone();
+
+    // a missed event makes the subscription resync, which then hangs on SUBSCRIBE
+    device.delay("SUBSCRIBE", Duration::from_millis(500));
+    let playing = property_set(&[("LastChange", &last_change(&[("TransportState", "PLAYING")]))]);
+    device.notify("AVTransport", 0, &playing).await;
+    device.notify("AVTransport", 5, &playing).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(device
+        .requests()
+        .iter()
+        .any(|req| req.method == "UNSUBSCRIBE"));
+    drop(av_transport);
+
+    let body = property_set(&[("LastChange", &last_change(&[("Volume", "10")]))]);
+    assert_eq!(
+        device
+            .notify_with_sid(&gena, "uuid:unknown", 0, &body)
+            .await,
+        412
+    );
+}
@@ -0,0 +1,608 @@
+mod support;
+
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use support::{last_change, property_set, FakeDevice, RecordedRequest};
+use upnp_client::{
+    device_client::DeviceClient,
+    eventing::Subscription,
+    media_renderer::MediaRendererClient,
+    types::{AVTransportEvent, Event, RenderingControlEvent, TransportState, TransportStatus},
+    Error,
+};
+
+async fn connect(device: &FakeDevice) -> MediaRendererClient {
+    let device_client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    MediaRendererClient::new(device_client)
+}
+
+async fn next_any_event(subscription: &mut Subscription) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), subscription.next())
+        .await
+        .expect("no event")
+        .unwrap()
+}
+
+/// Next event other than the raw `StateVariables` of a NOTIFY.
+async fn next_event(subscription: &mut Subscription) -> Event {
+    loop {
+        match next_any_event(subscription).await {
+            Event::StateVariables { .. } => continue,
+            event => return event,
+        }
+    }
+}
+
+fn transport_state(event: Event) -> (String, TransportState) {
+    match event {
+        Event::AVTransport(AVTransportEvent::TransportState {
+            sid,
+            transport_state,
+            ..
+        }) => (sid, transport_state),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn test_events_are_routed_per_subscription() {
+    let living_room = FakeDevice::start("media_renderer").await;
+    let kitchen = FakeDevice::start("media_renderer").await;
+    let mut living_room_events = connect(&living_room).await.subscribe().await.unwrap();
+    let mut kitchen_events = connect(&kitchen).await.subscribe().await.unwrap();
+    assert_eq!(living_room_events.sid(), "uuid:fake-sid-1");
+    assert_eq!(kitchen_events.sid(), "uuid:fake-sid-1");
+
+    let playing = property_set(&[("LastChange", &last_change(&[("TransportState", "PLAYING")]))]);
+    let stopped = property_set(&[("LastChange", &last_change(&[("TransportState", "STOPPED")]))]);
+    kitchen.notify("AVTransport", 0, &stopped).await;
+    living_room.notify("AVTransport", 0, &playing).await;
+
+    let (sid, state) = transport_state(next_event(&mut living_room_events).await);
+    assert_eq!(sid, "uuid:fake-sid-1");
+    assert_eq!(state, TransportState::Playing);
+    let (_, state) = transport_state(next_event(&mut kitchen_events).await);
+    assert_eq!(state, TransportState::Stopped);
+}
+
+#[tokio::test]
+async fn test_concurrent_subscriptions_on_one_device() {
+    let device = FakeDevice::start("media_renderer").await;
+    let device_client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut av_transport = device_client.subscribe("AVTransport").await.unwrap();
+    let mut rendering_control = device_client.subscribe("RenderingControl").await.unwrap();
+    assert_ne!(av_transport.sid(), rendering_control.sid());
+
+    let playing = property_set(&[("LastChange", &last_change(&[("TransportState", "PLAYING")]))]);
+    device.notify("RenderingControl", 0, &playing).await;
+    device.notify("AVTransport", 0, &playing).await;
+
+    match next_any_event(&mut rendering_control).await {
+        Event::StateVariables {
+            sid, service_id, ..
+        } => {
+            assert_eq!(sid, rendering_control.sid());
+            assert_eq!(service_id, "urn:upnp-org:serviceId:RenderingControl");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    let (sid, _) = transport_state(next_event(&mut av_transport).await);
+    assert_eq!(sid, av_transport.sid());
+}
+
+async fn unsubscribe_requests(device: &FakeDevice) -> Vec<RecordedRequest> {
+    for _ in 0..50 {
+        let requests: Vec<_> = device
+            .requests()
+            .into_iter()
+            .filter(|req| req.method == "UNSUBSCRIBE")
+            .collect();
+        if !requests.is_empty() {
+            return requests;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    vec![]
+}
+
+#[tokio::test]
+async fn test_subscription_handle() {
+    let device = FakeDevice::start("media_renderer").await;
+    let subscription = connect(&device).await.subscribe().await.unwrap();
+    assert_eq!(subscription.sid(), "uuid:fake-sid-1");
+    assert_eq!(subscription.timeout(), Some(Duration::from_secs(1800)));
+    assert_eq!(
+        subscription.service_id(),
+        "urn:upnp-org:serviceId:AVTransport"
+    );
+
+    subscription.unsubscribe().await.unwrap();
+    let requests = unsubscribe_requests(&device).await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/AVTransport/event");
+    assert_eq!(requests[0].header("sid"), Some("uuid:fake-sid-1"));
+    assert!(device.subscriptions().is_empty());
+}
+
+#[tokio::test]
+async fn test_dropping_subscription_unsubscribes() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("Second-infinite");
+    let subscription = connect(&device).await.subscribe().await.unwrap();
+    assert_eq!(subscription.timeout(), None);
+
+    drop(subscription);
+    let requests = unsubscribe_requests(&device).await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].header("sid"), Some("uuid:fake-sid-1"));
+}
+
+#[tokio::test]
+async fn test_rejected_subscribe_is_an_error() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond_raw("SUBSCRIBE", 412, "");
+    match connect(&device).await.subscribe().await {
+        Err(Error::HttpStatus { status, .. }) => assert_eq!(status, 412),
+        Err(e) => panic!("unexpected error {:?}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[tokio::test]
+async fn test_subscription_is_renewed_before_expiry() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("Second-1");
+    let subscription = connect(&device).await.subscribe().await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let renewals: Vec<_> = device
+        .requests()
+        .into_iter()
+        .filter(|req| req.method == "SUBSCRIBE" && req.header("sid").is_some())
+        .collect();
+    assert!(renewals.len() >= 2);
+    assert_eq!(renewals[0].header("sid"), Some("uuid:fake-sid-1"));
+    assert_eq!(renewals[0].header("callback"), None);
+    assert_eq!(subscription.sid(), "uuid:fake-sid-1");
+}
+
+#[tokio::test]
+async fn test_unreadable_timeout_is_renewed_as_requested() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("");
+    let subscription = connect(&device).await.subscribe().await.unwrap();
+    assert_eq!(subscription.timeout(), Some(Duration::from_secs(1800)));
+
+    device.subscription_timeout("Second-infinite");
+    let subscription = connect(&device).await.subscribe().await.unwrap();
+    assert_eq!(subscription.timeout(), None);
+}
+
+#[tokio::test]
+async fn test_zero_timeout_is_not_renewed_in_a_busy_loop() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("Second-0");
+    let _subscription = connect(&device).await.subscribe().await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let renewals = device
+        .requests()
+        .into_iter()
+        .filter(|req| req.method == "SUBSCRIBE" && req.header("sid").is_some())
+        .count();
+    assert!(renewals <= 3, "{} renewals", renewals);
+}
+
+#[tokio::test]
+async fn test_forgotten_subscription_is_made_again() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("Second-1");
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+    device.expire_subscriptions();
+
+    match next_event(&mut subscription).await {
+        Event::Resubscribed { previous_sid, sid } => {
+            assert_eq!(previous_sid, "uuid:fake-sid-1");
+            assert_eq!(sid, "uuid:fake-sid-2");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(subscription.sid(), "uuid:fake-sid-2");
+
+    let playing = property_set(&[("LastChange", &last_change(&[("TransportState", "PLAYING")]))]);
+    device.notify("AVTransport", 0, &playing).await;
+    let (sid, _) = transport_state(next_event(&mut subscription).await);
+    assert_eq!(sid, "uuid:fake-sid-2");
+}
+
+#[tokio::test]
+async fn test_renewal_failure_is_reported() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("Second-1");
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+    device.respond_raw("SUBSCRIBE", 500, "");
+
+    match next_event(&mut subscription).await {
+        Event::RenewalFailed { sid, error } => {
+            assert_eq!(sid, "uuid:fake-sid-1");
+            assert!(error.contains("500"));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn test_failed_renewal_is_retried_before_expiry() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.subscription_timeout("Second-2");
+    let subscribed = Instant::now();
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+    device.respond_raw("SUBSCRIBE", 500, "");
+
+    match next_event(&mut subscription).await {
+        Event::RenewalFailed { sid, .. } => assert_eq!(sid, "uuid:fake-sid-1"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    device.forget_response("SUBSCRIBE");
+
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    let renewals: Vec<_> = device
+        .requests()
+        .into_iter()
+        .filter(|req| req.method == "SUBSCRIBE" && req.header("sid").is_some())
+        .collect();
+    assert_eq!(renewals.len(), 2);
+    assert!(subscribed.elapsed() < Duration::from_secs(2));
+    assert_eq!(subscription.sid(), "uuid:fake-sid-1");
+
+    device
+        .notify("AVTransport", 0, &transport_state_change("PLAYING"))
+        .await;
+    let (sid, state) = transport_state(next_event(&mut subscription).await);
+    assert_eq!(sid, "uuid:fake-sid-1");
+    assert_eq!(state, TransportState::Playing);
+}
+
+fn transport_state_change(state: &str) -> String {
+    property_set(&[("LastChange", &last_change(&[("TransportState", state)]))])
+}
+
+#[tokio::test]
+async fn test_duplicate_and_late_events_are_dropped() {
+    let device = FakeDevice::start("media_renderer").await;
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+
+    device
+        .notify("AVTransport", 0, &transport_state_change("STOPPED"))
+        .await;
+    device
+        .notify("AVTransport", 1, &transport_state_change("TRANSITIONING"))
+        .await;
+    device
+        .notify("AVTransport", 1, &transport_state_change("TRANSITIONING"))
+        .await;
+    device
+        .notify("AVTransport", 0, &transport_state_change("STOPPED"))
+        .await;
+    device
+        .notify("AVTransport", 2, &transport_state_change("PLAYING"))
+        .await;
+
+    let states: Vec<TransportState> = vec![
+        transport_state(next_event(&mut subscription).await).1,
+        transport_state(next_event(&mut subscription).await).1,
+        transport_state(next_event(&mut subscription).await).1,
+    ];
+    assert_eq!(
+        states,
+        vec![
+            TransportState::Stopped,
+            TransportState::Transitioning,
+            TransportState::Playing
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_first_event_sets_the_sequence() {
+    let device = FakeDevice::start("media_renderer").await;
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+
+    device
+        .notify("AVTransport", 1, &transport_state_change("STOPPED"))
+        .await;
+    device
+        .notify("AVTransport", 2, &transport_state_change("PLAYING"))
+        .await;
+
+    assert_eq!(
+        transport_state(next_event(&mut subscription).await).1,
+        TransportState::Stopped
+    );
+    assert_eq!(
+        transport_state(next_event(&mut subscription).await).1,
+        TransportState::Playing
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(subscription.sid(), "uuid:fake-sid-1");
+    assert!(device
+        .requests()
+        .iter()
+        .all(|req| req.method != "UNSUBSCRIBE"));
+}
+
+#[tokio::test]
+async fn test_missed_event_triggers_resync() {
+    let device = FakeDevice::start("media_renderer").await;
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+
+    device
+        .notify("AVTransport", 0, &transport_state_change("STOPPED"))
+        .await;
+    device
+        .notify("AVTransport", 3, &transport_state_change("PLAYING"))
+        .await;
+
+    assert_eq!(
+        transport_state(next_event(&mut subscription).await).1,
+        TransportState::Stopped
+    );
+    assert_eq!(
+        transport_state(next_event(&mut subscription).await).1,
+        TransportState::Playing
+    );
+    match next_event(&mut subscription).await {
+        Event::Resynced { previous_sid, sid } => {
+            assert_eq!(previous_sid, "uuid:fake-sid-1");
+            assert_eq!(sid, "uuid:fake-sid-2");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    let requests = unsubscribe_requests(&device).await;
+    assert_eq!(requests[0].header("sid"), Some("uuid:fake-sid-1"));
+
+    device
+        .notify("AVTransport", 0, &transport_state_change("PAUSED_PLAYBACK"))
+        .await;
+    let (sid, state) = transport_state(next_event(&mut subscription).await);
+    assert_eq!(sid, "uuid:fake-sid-2");
+    assert_eq!(state, TransportState::PausedPlayback);
+}
+
+#[tokio::test]
+async fn test_state_variables_of_any_service() {
+    let device = FakeDevice::start("media_renderer").await;
+    let device_client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut subscription = device_client.subscribe("ConnectionManager").await.unwrap();
+
+    let body = property_set(&[
+        ("SourceProtocolInfo", ""),
+        ("SinkProtocolInfo", "http-get:*:audio/flac:*"),
+        ("CurrentConnectionIDs", "0"),
+    ]);
+    device.notify("ConnectionManager", 0, &body).await;
+
+    match next_any_event(&mut subscription).await {
+        Event::StateVariables {
+            sid,
+            service_id,
+            values,
+        } => {
+            assert_eq!(sid, "uuid:fake-sid-1");
+            assert_eq!(service_id, "urn:upnp-org:serviceId:ConnectionManager");
+            assert_eq!(
+                values,
+                vec![
+                    ("SourceProtocolInfo".to_string(), "".to_string()),
+                    (
+                        "SinkProtocolInfo".to_string(),
+                        "http-get:*:audio/flac:*".to_string()
+                    ),
+                    ("CurrentConnectionIDs".to_string(), "0".to_string()),
+                ]
+            );
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn test_rendering_control_events() {
+    let device = FakeDevice::start("media_renderer").await;
+    let mut events = connect(&device).await.subscribe_all().await.unwrap();
+    assert_eq!(events.av_transport().sid(), "uuid:fake-sid-1");
+    assert_eq!(events.rendering_control().sid(), "uuid:fake-sid-2");
+
+    let rendering_control = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/RCS/"><InstanceID val="0"><Volume channel="Master" val="24"/><Volume channel="LF" val="20"/><VolumeDB channel="Master" val="-1280"/><Mute channel="Master" val="1"/><Loudness channel="Master" val="0"/><PresetNameList val="FactoryDefaults,InstallationDefaults"/><Brightness val="50"/></InstanceID></Event>"#;
+    device
+        .notify(
+            "RenderingControl",
+            0,
+            &property_set(&[("LastChange", rendering_control)]),
+        )
+        .await;
+    device
+        .notify("AVTransport", 0, &transport_state_change("PLAYING"))
+        .await;
+
+    let mut rendering_control_events = vec![];
+    let mut transport_states = vec![];
+    while rendering_control_events.len() < 7 || transport_states.is_empty() {
+        match tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("no event")
+            .unwrap()
+        {
+            Event::RenderingControl(event) => rendering_control_events.push(event),
+            Event::AVTransport(event) => transport_states.push(event),
+            _ => {}
+        }
+    }
+
+    assert!(matches!(
+        &rendering_control_events[0],
+        RenderingControlEvent::Volume { sid, instance_id: 0, channel, volume: 24 }
+            if sid == "uuid:fake-sid-2" && channel == "Master"
+    ));
+    assert!(matches!(
+        &rendering_control_events[1],
+        RenderingControlEvent::Volume { channel, volume: 20, .. } if channel == "LF"
+    ));
+    assert!(matches!(
+        &rendering_control_events[2],
+        RenderingControlEvent::VolumeDB {
+            volume_db: -1280,
+            ..
+        }
+    ));
+    assert!(matches!(
+        &rendering_control_events[3],
+        RenderingControlEvent::Mute { mute: true, .. }
+    ));
+    assert!(matches!(
+        &rendering_control_events[4],
+        RenderingControlEvent::Loudness {
+            loudness: false,
+            ..
+        }
+    ));
+    match &rendering_control_events[5] {
+        RenderingControlEvent::PresetNameList { presets, .. } => {
+            assert_eq!(presets, &vec!["FactoryDefaults", "InstallationDefaults"])
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(matches!(
+        &rendering_control_events[6],
+        RenderingControlEvent::Other { name, value, .. } if name == "Brightness" && value == "50"
+    ));
+    assert!(matches!(
+        &transport_states[0],
+        AVTransportEvent::TransportState { transport_state, .. } if *transport_state == TransportState::Playing
+    ));
+}
+
+#[tokio::test]
+async fn test_av_transport_variables_of_every_instance() {
+    let device = FakeDevice::start("media_renderer").await;
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+
+    let last_change = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/"><InstanceID val="0"><TransportStatus val="OK"/><NumberOfTracks val="3"/><CurrentTrack val="2"/><CurrentTrackDuration val="0:03:25"/><CurrentTrackURI val="http://music/2.flac"/><TransportPlaySpeed val="1"/><CurrentTransportActions val="Play,Stop,Seek"/><X_VendorState val="idle"/></InstanceID><InstanceID val="1"><TransportState val="STOPPED"/><NextAVTransportURI val="http://music/3.flac"/></InstanceID></Event>"#;
+    device
+        .notify(
+            "AVTransport",
+            0,
+            &property_set(&[("LastChange", last_change)]),
+        )
+        .await;
+
+    let mut events = vec![];
+    for _ in 0..10 {
+        match next_event(&mut subscription).await {
+            Event::AVTransport(event) => events.push(event),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    assert!(matches!(
+        &events[0],
+        AVTransportEvent::TransportStatus { instance_id: 0, transport_status, .. } if *transport_status == TransportStatus::Ok
+    ));
+    assert!(matches!(
+        &events[1],
+        AVTransportEvent::NumberOfTracks {
+            number_of_tracks: 3,
+            ..
+        }
+    ));
+    assert!(matches!(
+        &events[2],
+        AVTransportEvent::CurrentTrack { track: 2, .. }
+    ));
+    assert!(matches!(
+        &events[3],
+        AVTransportEvent::CurrentTrackDuration { duration, .. } if duration == "0:03:25"
+    ));
+    assert!(matches!(
+        &events[4],
+        AVTransportEvent::CurrentTrackURI { uri, .. } if uri == "http://music/2.flac"
+    ));
+    assert!(matches!(
+        &events[5],
+        AVTransportEvent::TransportPlaySpeed { speed, .. } if speed == "1"
+    ));
+    match &events[6] {
+        AVTransportEvent::CurrentTransportActions { actions, .. } => {
+            assert_eq!(actions, &vec!["Play", "Stop", "Seek"])
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(matches!(
+        &events[7],
+        AVTransportEvent::Other { name, value, .. } if name == "X_VendorState" && value == "idle"
+    ));
+    assert!(matches!(
+        &events[8],
+        AVTransportEvent::TransportState { instance_id: 1, transport_state, .. } if *transport_state == TransportState::Stopped
+    ));
+    assert!(matches!(
+        &events[9],
+        AVTransportEvent::NextAVTransportURI { instance_id: 1, uri, .. } if uri == "http://music/3.flac"
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_notify_requests_are_rejected() {
+    let device = FakeDevice::start("media_renderer").await;
+    let mut subscription = connect(&device).await.subscribe().await.unwrap();
+    let gena = &device.subscriptions()[0];
+    let body = transport_state_change("PLAYING");
+
+    let missing_nt = [("NTS", "upnp:propchange"), ("SID", "uuid:fake-sid-1")];
+    assert_eq!(device.notify_raw(gena, &missing_nt, &body).await, 400);
+    let invalid_nts = [
+        ("NT", "upnp:event"),
+        ("NTS", "ssdp:alive"),
+        ("SID", "uuid:fake-sid-1"),
+    ];
+    assert_eq!(device.notify_raw(gena, &invalid_nts, &body).await, 412);
+    let missing_sid = [("NT", "upnp:event"), ("NTS", "upnp:propchange")];
+    assert_eq!(device.notify_raw(gena, &missing_sid, &body).await, 412);
+    assert_eq!(
+        device.notify_with_sid(gena, "uuid:unknown", 0, &body).await,
+        412
+    );
+
+    assert_eq!(device.notify("AVTransport", 0, "<e:propertyset").await, 200);
+    match next_event(&mut subscription).await {
+        Event::InvalidNotification { sid, .. } => assert_eq!(sid, "uuid:fake-sid-1"),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    let broken_last_change = property_set(&[("LastChange", "<Event><InstanceID val=\"x\"/>")]);
+    assert_eq!(
+        device.notify("AVTransport", 1, &broken_last_change).await,
+        200
+    );
+    match next_event(&mut subscription).await {
+        Event::InvalidNotification { sid, .. } => assert_eq!(sid, "uuid:fake-sid-1"),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    assert_eq!(device.notify("AVTransport", 2, &body).await, 200);
+    let (_, state) = transport_state(next_event(&mut subscription).await);
+    assert_eq!(state, TransportState::Playing);
+}
@@ -0,0 +1,786 @@
+mod support;
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use support::{last_change, property_set, upnp_error, FakeDevice};
+use tokio::sync::watch;
+use upnp_client::{
+    device_client::DeviceClient,
+    media_renderer::{MediaEvents, MediaRendererClient},
+    types::{
+        LoadOptions, MediaInfo, Metadata, ObjectClass, PictureControl, PlayMode, Progress,
+        RendererState, SeekTarget, TransportState, TransportStatus,
+    },
+    Error,
+};
+
+async fn connect(device: &FakeDevice) -> MediaRendererClient {
+    let device_client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    MediaRendererClient::new(device_client)
+}
+
+#[tokio::test]
+async fn test_load_and_autoplay() {
+    let device = FakeDevice::start("media_renderer").await;
+    let media_renderer = connect(&device).await;
+
+    let options = LoadOptions {
+        content_type: Some("audio/mpeg".to_string()),
+        metadata: Some(Metadata {
+            title: "Track".to_string(),
+            artist: Some("Artist".to_string()),
+            ..Default::default()
+        }),
+        object_class: Some(ObjectClass::Audio),
+        autoplay: true,
+        ..Default::default()
+    };
+    media_renderer
+        .load("http://127.0.0.1/track.mp3", options)
+        .await
+        .unwrap();
+
+    let set_uri = device.action_requests("SetAVTransportURI");
+    assert_eq!(set_uri.len(), 1);
+    assert_eq!(
+        set_uri[0].argument("CurrentURI"),
+        Some("http://127.0.0.1/track.mp3".to_string())
+    );
+    let play = device.action_requests("Play");
+    assert_eq!(play.len(), 1);
+    assert_eq!(play[0].argument("Speed"), Some("1".to_string()));
+}
+
+#[tokio::test]
+async fn test_get_volume() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "27")]);
+    let media_renderer = connect(&device).await;
+
+    assert_eq!(media_renderer.get_volume().await.unwrap(), 27);
+}
+
+#[tokio::test]
+async fn test_get_transport_info() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetTransportInfo",
+        &[
+            ("CurrentTransportState", "PLAYING"),
+            ("CurrentTransportStatus", "OK"),
+            ("CurrentSpeed", "1"),
+        ],
+    );
+    let media_renderer = connect(&device).await;
+
+    let transport_info = media_renderer.get_transport_info().await.unwrap();
+    assert_eq!(
+        transport_info.current_transport_state,
+        TransportState::Playing
+    );
+    assert_eq!(transport_info.current_transport_status, TransportStatus::Ok);
+    assert_eq!(transport_info.current_speed, "1");
+}
+
+#[tokio::test]
+async fn test_get_position_and_duration() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetPositionInfo", &[("RelTime", "00:01:05")]);
+    device.respond("GetMediaInfo", &[("MediaDuration", "00:03:20")]);
+    let media_renderer = connect(&device).await;
+
+    assert_eq!(media_renderer.get_position().await.unwrap(), 65);
+    assert_eq!(media_renderer.get_duration().await.unwrap(), 200);
+}
+
+#[tokio::test]
+async fn test_get_media_info() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetMediaInfo",
+        &[
+            ("NrTracks", "1"),
+            ("MediaDuration", "1:02:03"),
+            ("CurrentURI", "http://127.0.0.1/movie.mkv"),
+            ("CurrentURIMetaData", ""),
+            ("NextURI", "NOT_IMPLEMENTED"),
+            ("NextURIMetaData", "NOT_IMPLEMENTED"),
+            ("PlayMedium", "NETWORK"),
+            ("RecordMedium", "NOT_IMPLEMENTED"),
+            ("WriteStatus", "NOT_IMPLEMENTED"),
+        ],
+    );
+    let media_renderer = connect(&device).await;
+
+    let media_info = media_renderer.get_media_info().await.unwrap();
+    assert_eq!(
+        media_info,
+        MediaInfo {
+            nr_tracks: 1,
+            media_duration: Some(Duration::from_secs(3723)),
+            current_uri: Some("http://127.0.0.1/movie.mkv".to_string()),
+            play_medium: Some("NETWORK".to_string()),
+            ..Default::default()
+        }
+    );
+    assert_eq!(media_renderer.get_duration().await.unwrap(), 3723);
+}
+
+async fn wait_for(
+    state: &mut watch::Receiver<RendererState>,
+    done: impl Fn(&RendererState) -> bool,
+) -> RendererState {
+    tokio::time::timeout(Duration::from_secs(5), state.wait_for(|state| done(state)))
+        .await
+        .expect("state not reached")
+        .unwrap()
+        .clone()
+}
+
+#[tokio::test]
+async fn test_watch_state() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetTransportInfo",
+        &[
+            ("CurrentTransportState", "STOPPED"),
+            ("CurrentTransportStatus", "OK"),
+            ("CurrentSpeed", "1"),
+        ],
+    );
+    device.respond("GetVolume", &[("CurrentVolume", "30")]);
+    device.respond("GetMediaInfo", &[("MediaDuration", "00:40:00")]);
+    device.respond(
+        "GetPositionInfo",
+        &[
+            ("RelTime", "00:00:05"),
+            ("TrackDuration", "00:04:00"),
+            ("TrackURI", "http://127.0.0.1/first.mp3"),
+        ],
+    );
+    let media_renderer = connect(&device).await;
+
+    let mut state = media_renderer
+        .watch_state(Duration::from_millis(50))
+        .await
+        .unwrap();
+    let initial = wait_for(&mut state, |state| state.position.is_some()).await;
+    assert_eq!(initial.transport_state, Some(TransportState::Stopped));
+    assert_eq!(initial.volume, Some(30));
+    assert_eq!(initial.duration, Some(Duration::from_secs(240)));
+    assert_eq!(initial.position, Some(Duration::from_secs(5)));
+    assert_eq!(
+        initial.current_uri.as_deref(),
+        Some("http://127.0.0.1/first.mp3")
+    );
+
+    let av_transport = last_change(&[
+        ("TransportState", "PLAYING"),
+        ("AVTransportURI", "http://127.0.0.1/playlist.m3u"),
+        ("CurrentTrackURI", "http://127.0.0.1/track.mp3"),
+        ("CurrentMediaDuration", "0:40:00"),
+        ("CurrentTrackDuration", "0:03:00"),
+        ("CurrentPlayMode", "SHUFFLE"),
+    ]);
+    device
+        .notify(
+            "AVTransport",
+            0,
+            &property_set(&[("LastChange", &av_transport)]),
+        )
+        .await;
+    let rendering_control = r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/RCS/"><InstanceID val="0"><Volume channel="Master" val="12"/><Mute channel="Master" val="1"/></InstanceID></Event>"#;
+    device
+        .notify(
+            "RenderingControl",
+            0,
+            &property_set(&[("LastChange", rendering_control)]),
+        )
+        .await;
+
+    let playing = wait_for(&mut state, |state| state.mute == Some(true)).await;
+    assert_eq!(playing.transport_state, Some(TransportState::Playing));
+    assert_eq!(
+        playing.current_uri.as_deref(),
+        Some("http://127.0.0.1/track.mp3")
+    );
+    assert_eq!(playing.duration, Some(Duration::from_secs(180)));
+    assert_eq!(playing.play_mode, Some(PlayMode::Shuffle));
+    assert_eq!(playing.volume, Some(12));
+
+    device.respond("GetPositionInfo", &[("RelTime", "00:00:09")]);
+    let later = wait_for(&mut state, |state| {
+        state.position == Some(Duration::from_secs(9))
+    })
+    .await;
+    assert_eq!(later.transport_state, Some(TransportState::Playing));
+}
+
+#[tokio::test]
+async fn test_watch_state_with_zero_interval() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetPositionInfo", &[("RelTime", "00:00:05")]);
+    let media_renderer = connect(&device).await;
+
+    let mut state = media_renderer.watch_state(Duration::ZERO).await.unwrap();
+    let state = wait_for(&mut state, |state| state.position.is_some()).await;
+    assert_eq!(state.position, Some(Duration::from_secs(5)));
+}
+
+#[tokio::test]
+async fn test_position_stream() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetTransportInfo",
+        &[
+            ("CurrentTransportState", "PLAYING"),
+            ("CurrentTransportStatus", "OK"),
+            ("CurrentSpeed", "1"),
+        ],
+    );
+    device.respond(
+        "GetPositionInfo",
+        &[("RelTime", "0:00:10"), ("TrackDuration", "0:00:20")],
+    );
+    let media_renderer = connect(&device).await;
+
+    let progress = media_renderer.position_stream(Duration::from_millis(50));
+    futures_util::pin_mut!(progress);
+    let first = progress.next().await.unwrap();
+    assert!(first.position >= Duration::from_secs(10));
+    assert_eq!(first.duration, Some(Duration::from_secs(20)));
+    assert!(first.percentage.unwrap() >= 50.0);
+
+    let later = progress.by_ref().skip(4).next().await.unwrap();
+    assert!(later.position >= first.position + Duration::from_millis(200));
+    assert!(later.position < Duration::from_secs(11));
+    assert_eq!(device.action_requests("GetPositionInfo").len(), 1);
+}
+
+#[tokio::test]
+async fn test_position_stream_at_end_of_track() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetTransportInfo",
+        &[
+            ("CurrentTransportState", "PLAYING"),
+            ("CurrentTransportStatus", "OK"),
+            ("CurrentSpeed", "1"),
+        ],
+    );
+    device.respond(
+        "GetPositionInfo",
+        &[("RelTime", "0:00:20"), ("TrackDuration", "0:00:20")],
+    );
+    let media_renderer = connect(&device).await;
+
+    let progress = media_renderer.position_stream(Duration::from_millis(50));
+    futures_util::pin_mut!(progress);
+    let progress: Vec<Progress> = progress.take(10).collect().await;
+    assert!(progress
+        .iter()
+        .all(|progress| progress.position == Duration::from_secs(20)));
+    assert_eq!(device.action_requests("GetPositionInfo").len(), 1);
+}
+
+#[tokio::test]
+async fn test_position_stream_with_zero_interval() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetPositionInfo", &[("RelTime", "0:00:10")]);
+    let media_renderer = connect(&device).await;
+
+    let progress = media_renderer.position_stream(Duration::ZERO);
+    futures_util::pin_mut!(progress);
+    let progress: Vec<Progress> = progress.take(3).collect().await;
+    assert_eq!(progress.len(), 3);
+}
+
+#[tokio::test]
+async fn test_position_stream_paused() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetTransportInfo",
+        &[
+            ("CurrentTransportState", "PAUSED_PLAYBACK"),
+            ("CurrentTransportStatus", "OK"),
+            ("CurrentSpeed", "1"),
+        ],
+    );
+    device.respond(
+        "GetPositionInfo",
+        &[("RelTime", "0:01:00"), ("TrackDuration", "NOT_IMPLEMENTED")],
+    );
+    let media_renderer = connect(&device).await;
+
+    let progress = media_renderer.position_stream(Duration::from_millis(20));
+    futures_util::pin_mut!(progress);
+    let progress: Vec<Progress> = progress.take(3).collect().await;
+    for progress in progress {
+        assert_eq!(progress.position, Duration::from_secs(60));
+        assert_eq!(progress.duration, None);
+        assert_eq!(progress.percentage, None);
+    }
+}
+
+#[tokio::test]
+async fn test_media_events() {
+    let device = FakeDevice::start("media_renderer").await;
+    let media_renderer = connect(&device).await;
+    let events = media_renderer.media_events().await.unwrap();
+    futures_util::pin_mut!(events);
+
+    let loading = last_change(&[
+        ("TransportState", "TRANSITIONING"),
+        ("CurrentTrackURI", "http://127.0.0.1/track.mp3"),
+    ]);
+    device
+        .notify("AVTransport", 0, &property_set(&[("LastChange", &loading)]))
+        .await;
+    let playing = last_change(&[
+        ("TransportState", "PLAYING"),
+        ("TransportPlaySpeed", "2"),
+        ("CurrentPlayMode", "REPEAT_ALL"),
+        ("NumberOfTracks", "1"),
+    ]);
+    device
+        .notify("AVTransport", 1, &property_set(&[("LastChange", &playing)]))
+        .await;
+    let stopped = last_change(&[
+        ("TransportState", "NO_MEDIA_PRESENT"),
+        ("TransportStatus", "ERROR_OCCURRED"),
+    ]);
+    device
+        .notify("AVTransport", 2, &property_set(&[("LastChange", &stopped)]))
+        .await;
+
+    let events: Vec<MediaEvents> =
+        tokio::time::timeout(Duration::from_secs(5), events.take(7).collect::<Vec<_>>())
+            .await
+            .unwrap();
+    assert_eq!(
+        events,
+        vec![
+            MediaEvents::Loading,
+            MediaEvents::TrackChanged("http://127.0.0.1/track.mp3".to_string()),
+            MediaEvents::Playing,
+            MediaEvents::SpeedChanged("2".to_string()),
+            MediaEvents::PlayModeChanged(PlayMode::RepeatAll),
+            MediaEvents::Stopped,
+            MediaEvents::Status(TransportStatus::ErrorOccurred),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_seek_modes() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetCurrentTransportActions",
+        &[("Actions", "Play,Stop,Seek")],
+    );
+    device.respond(
+        "GetPositionInfo",
+        &[("RelTime", "0:00:05"), ("TrackDuration", "0:00:20")],
+    );
+    let media_renderer = connect(&device).await;
+
+    media_renderer
+        .seek_to(SeekTarget::AbsTime(Duration::from_millis(61_500)))
+        .await
+        .unwrap();
+    media_renderer
+        .seek_to(SeekTarget::TrackNr(3))
+        .await
+        .unwrap();
+    media_renderer
+        .seek_to(SeekTarget::RelByte(1024))
+        .await
+        .unwrap();
+    media_renderer.seek_by(-10.0).await.unwrap();
+    media_renderer.seek_by(30.0).await.unwrap();
+
+    let seeks: Vec<(Option<String>, Option<String>)> = device
+        .action_requests("Seek")
+        .iter()
+        .map(|seek| (seek.argument("Unit"), seek.argument("Target")))
+        .collect();
+    let expected = [
+        ("ABS_TIME", "00:01:01.500"),
+        ("TRACK_NR", "3"),
+        ("X_DLNA_REL_BYTE", "1024"),
+        ("REL_TIME", "00:00:00"),
+        ("REL_TIME", "00:00:20"),
+    ];
+    let expected: Vec<(Option<String>, Option<String>)> = expected
+        .iter()
+        .map(|(unit, target)| (Some(unit.to_string()), Some(target.to_string())))
+        .collect();
+    assert_eq!(seeks, expected);
+}
+
+#[tokio::test]
+async fn test_seek_by_out_of_range() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetPositionInfo",
+        &[("RelTime", "0:00:10"), ("TrackDuration", "NOT_IMPLEMENTED")],
+    );
+    let media_renderer = connect(&device).await;
+
+    for seconds in [f64::INFINITY, f64::NAN, 1e20] {
+        let result = media_renderer.seek_by(seconds).await;
+        assert!(matches!(result, Err(Error::ValueNotAllowed { name, .. }) if name == "seconds"));
+    }
+    assert!(device.action_requests("Seek").is_empty());
+}
+
+#[tokio::test]
+async fn test_seek_not_allowed() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetCurrentTransportActions",
+        &[("Actions", "Play,Stop,X_DLNA_SeekTime")],
+    );
+    let media_renderer = connect(&device).await;
+
+    let result = media_renderer.seek_to(SeekTarget::RelByte(1024)).await;
+    assert!(matches!(result, Err(Error::ActionNotAllowed(action)) if action == "Seek"));
+    assert!(device.action_requests("Seek").is_empty());
+
+    media_renderer.seek(42).await.unwrap();
+    let seeks = device.action_requests("Seek");
+    assert_eq!(seeks.len(), 1);
+    assert_eq!(seeks[0].argument("Target"), Some("00:00:42".to_string()));
+}
+
+#[tokio::test]
+async fn test_seek_when_transport_actions_are_unknown() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetCurrentTransportActions",
+        &[("Actions", "NOT_IMPLEMENTED")],
+    );
+    let media_renderer = connect(&device).await;
+
+    media_renderer.seek(42).await.unwrap();
+    assert_eq!(device.action_requests("Seek").len(), 1);
+
+    device.respond_raw(
+        "GetCurrentTransportActions",
+        500,
+        &upnp_error(602, "Optional Action Not Implemented"),
+    );
+    media_renderer.seek(43).await.unwrap();
+    let seeks = device.action_requests("Seek");
+    assert_eq!(seeks.len(), 2);
+    assert_eq!(seeks[1].argument("Target"), Some("00:00:43".to_string()));
+}
+
+#[tokio::test]
+async fn test_set_play_mode() {
+    let device = FakeDevice::start("media_renderer").await;
+    let media_renderer = connect(&device).await;
+
+    media_renderer
+        .set_play_mode(PlayMode::RepeatAll)
+        .await
+        .unwrap();
+    let result = media_renderer.set_play_mode(PlayMode::Random).await;
+    assert!(matches!(
+        result,
+        Err(Error::ValueNotAllowed { name, value }) if name == "CurrentPlayMode" && value == "RANDOM"
+    ));
+
+    let requests = device.action_requests("SetPlayMode");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].argument("NewPlayMode"),
+        Some("REPEAT_ALL".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_transport_settings_and_capabilities() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetTransportSettings",
+        &[
+            ("PlayMode", "SHUFFLE"),
+            ("RecQualityMode", "NOT_IMPLEMENTED"),
+        ],
+    );
+    device.respond(
+        "GetDeviceCapabilities",
+        &[
+            ("PlayMedia", "NETWORK,HDD"),
+            ("RecMedia", "NOT_IMPLEMENTED"),
+            ("RecQualityModes", ""),
+        ],
+    );
+    device.respond("GetCurrentTransportActions", &[("Actions", "Play, Seek")]);
+    let media_renderer = connect(&device).await;
+
+    let settings = media_renderer.get_transport_settings().await.unwrap();
+    assert_eq!(settings.play_mode, PlayMode::Shuffle);
+    assert_eq!(settings.rec_quality_mode, None);
+
+    let capabilities = media_renderer.get_device_capabilities().await.unwrap();
+    assert_eq!(capabilities.play_media, vec!["NETWORK", "HDD"]);
+    assert!(capabilities.rec_media.is_empty());
+    assert!(capabilities.rec_quality_modes.is_empty());
+
+    assert_eq!(
+        media_renderer
+            .get_current_transport_actions()
+            .await
+            .unwrap(),
+        vec!["Play", "Seek"]
+    );
+}
+
+#[tokio::test]
+async fn test_volume_controls() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "95")]);
+    device.respond("GetMute", &[("CurrentMute", "1")]);
+    device.respond("GetLoudness", &[("CurrentLoudness", "false")]);
+    device.respond("GetVolumeDB", &[("CurrentVolume", "-2560")]);
+    device.respond(
+        "GetVolumeDBRange",
+        &[("MinValue", "-10240"), ("MaxValue", "0")],
+    );
+    let media_renderer = connect(&device).await;
+
+    assert!(media_renderer.get_mute().await.unwrap());
+    assert!(!media_renderer.get_loudness().await.unwrap());
+    assert_eq!(media_renderer.get_volume_db().await.unwrap(), -2560);
+    assert_eq!(
+        media_renderer.get_volume_db_range().await.unwrap(),
+        (-10240, 0)
+    );
+    media_renderer.set_mute(false).await.unwrap();
+    media_renderer.set_loudness(true).await.unwrap();
+    media_renderer.set_volume_db(-512).await.unwrap();
+    media_renderer.set_channel_volume("LF", 30).await.unwrap();
+
+    assert_eq!(media_renderer.volume_up(10).await.unwrap(), 100);
+    assert_eq!(media_renderer.volume_down(5).await.unwrap(), 90);
+
+    assert!(matches!(
+        media_renderer.set_channel_volume("CF", 30).await,
+        Err(Error::ValueNotAllowed { name, .. }) if name == "A_ARG_TYPE_Channel"
+    ));
+    assert!(matches!(
+        media_renderer.set_volume(101).await,
+        Err(Error::ValueNotAllowed { name, .. }) if name == "Volume"
+    ));
+    assert!(matches!(
+        media_renderer.set_volume_db(-20000).await,
+        Err(Error::ValueNotAllowed { name, .. }) if name == "VolumeDB"
+    ));
+
+    let arguments = |action: &str, name: &str| -> Vec<(Option<String>, Option<String>)> {
+        device
+            .action_requests(action)
+            .iter()
+            .map(|request| (request.argument("Channel"), request.argument(name)))
+            .collect()
+    };
+    let some = |channel: &str, value: &str| (Some(channel.to_string()), Some(value.to_string()));
+    assert_eq!(
+        arguments("SetMute", "DesiredMute"),
+        vec![some("Master", "0")]
+    );
+    assert_eq!(
+        arguments("SetLoudness", "DesiredLoudness"),
+        vec![some("Master", "1")]
+    );
+    assert_eq!(
+        arguments("SetVolumeDB", "DesiredVolume"),
+        vec![some("Master", "-512")]
+    );
+    assert_eq!(
+        arguments("SetVolume", "DesiredVolume"),
+        vec![
+            some("LF", "30"),
+            some("Master", "100"),
+            some("Master", "90")
+        ]
+    );
+}
+
+fn volumes(device: &FakeDevice) -> Vec<u32> {
+    device
+        .action_requests("SetVolume")
+        .iter()
+        .map(|request| request.argument("DesiredVolume").unwrap().parse().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_fade_volume() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "40")]);
+    let media_renderer = connect(&device).await;
+
+    // the fake renderer keeps answering 40, like one that ignores or clamps changes
+    let volume = media_renderer
+        .fade_volume(150, Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert_eq!(volume, 40);
+
+    let steps = volumes(&device);
+    assert!(steps.len() > 1);
+    assert!(steps.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(steps.last(), Some(&100));
+}
+
+#[tokio::test]
+async fn test_cancel_fade() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "80")]);
+    let media_renderer = connect(&device).await;
+
+    let fade = media_renderer.fade_volume(0, Duration::from_secs(2));
+    assert!(tokio::time::timeout(Duration::from_millis(400), fade)
+        .await
+        .is_err());
+    let cancelled = volumes(&device);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(volumes(&device), cancelled);
+    assert!(cancelled.iter().all(|volume| *volume > 0));
+}
+
+#[tokio::test]
+async fn test_fade_out_and_stop() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "20")]);
+    let media_renderer = connect(&device).await;
+
+    media_renderer
+        .fade_out_and_stop(Duration::from_millis(200))
+        .await
+        .unwrap();
+    assert_eq!(device.action_requests("Stop").len(), 1);
+    let fade_out = volumes(&device);
+    assert_eq!(fade_out[fade_out.len() - 2..], [0, 20]);
+
+    media_renderer
+        .play_and_fade_in(Duration::from_millis(200))
+        .await
+        .unwrap();
+    assert_eq!(device.action_requests("Play").len(), 1);
+    let fade_in = volumes(&device)[fade_out.len()..].to_vec();
+    assert_eq!(fade_in.first(), Some(&0));
+    assert_eq!(fade_in.last(), Some(&20));
+}
+
+#[tokio::test]
+async fn test_missing_argument_is_named() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetMute", &[]);
+    let media_renderer = connect(&device).await;
+
+    let error = media_renderer.get_mute().await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid response from device: GetMute response missing CurrentMute"
+    );
+}
+
+#[tokio::test]
+async fn test_fade_volume_db_with_inverted_range() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "GetVolumeDBRange",
+        &[("MinValue", "0"), ("MaxValue", "-25600")],
+    );
+    device.respond("GetVolumeDB", &[("CurrentVolume", "-2560")]);
+    let media_renderer = connect(&device).await;
+
+    let result = media_renderer
+        .fade_volume_db(0, Duration::from_millis(200))
+        .await;
+    assert!(matches!(result, Err(Error::Parse(_))));
+    assert!(device.action_requests("SetVolumeDB").is_empty());
+}
+
+#[tokio::test]
+async fn test_presets_and_picture_controls() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond(
+        "ListPresets",
+        &[(
+            "CurrentPresetNameList",
+            "FactoryDefaults,InstallationDefaults",
+        )],
+    );
+    device.respond("GetBrightness", &[("CurrentBrightness", "55")]);
+    device.respond("GetVerticalKeystone", &[("CurrentVerticalKeystone", "-12")]);
+    let media_renderer = connect(&device).await;
+
+    assert_eq!(
+        media_renderer.list_presets().await.unwrap(),
+        vec!["FactoryDefaults", "InstallationDefaults"]
+    );
+    media_renderer
+        .select_preset("FactoryDefaults")
+        .await
+        .unwrap();
+    assert!(matches!(
+        media_renderer.select_preset("Cinema").await,
+        Err(Error::ValueNotAllowed { .. })
+    ));
+    let selected = device.action_requests("SelectPreset");
+    assert_eq!(selected.len(), 1);
+    assert_eq!(
+        selected[0].argument("PresetName"),
+        Some("FactoryDefaults".to_string())
+    );
+
+    assert_eq!(
+        media_renderer
+            .get_picture_control(PictureControl::Brightness)
+            .await
+            .unwrap(),
+        55
+    );
+    assert_eq!(
+        media_renderer
+            .get_picture_control(PictureControl::VerticalKeystone)
+            .await
+            .unwrap(),
+        -12
+    );
+    let range = media_renderer
+        .get_picture_control_range(PictureControl::HorizontalKeystone)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((range.minimum, range.maximum), (-100, 100));
+
+    media_renderer
+        .set_picture_control(PictureControl::HorizontalKeystone, -40)
+        .await
+        .unwrap();
+    assert!(matches!(
+        media_renderer
+            .set_picture_control(PictureControl::Contrast, 120)
+            .await,
+        Err(Error::ValueNotAllowed { name, .. }) if name == "Contrast"
+    ));
+    let keystone = device.action_requests("SetHorizontalKeystone");
+    assert_eq!(keystone.len(), 1);
+    assert_eq!(
+        keystone[0].argument("DesiredHorizontalKeystone"),
+        Some("-40".to_string())
+    );
+    assert!(device.action_requests("SetContrast").is_empty());
+}
@@ -0,0 +1,48 @@
+mod support;
+
+use support::FakeDevice;
+use upnp_client::{
+    device_client::DeviceClient, media_server::MediaServerClient, types::ObjectClass,
+};
+
+const DIDL: &str = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><container id="1" parentID="0" restricted="1"><dc:title>Music</dc:title><upnp:class>object.container</upnp:class></container><item id="2" parentID="0" restricted="1"><dc:title>Track</dc:title><upnp:artist>Artist</upnp:artist><upnp:album>Album</upnp:album><upnp:class>object.item.audioItem.musicTrack</upnp:class><res protocolInfo="http-get:*:audio/mpeg:*" size="1024" duration="0:03:20.000">http://127.0.0.1/track.mp3</res></item></DIDL-Lite>"#;
+
+#[tokio::test]
+async fn test_browse() {
+    let device = FakeDevice::start("media_server").await;
+    device.respond(
+        "Browse",
+        &[
+            ("Result", DIDL),
+            ("NumberReturned", "2"),
+            ("TotalMatches", "2"),
+            ("UpdateID", "1"),
+        ],
+    );
+    let device_client = DeviceClient::new(&device.location())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let media_server = MediaServerClient::new(device_client);
+
+    let (containers, items) = media_server
+        .browse("0", "BrowseDirectChildren")
+        .await
+        .unwrap();
+
+    assert_eq!(containers.len(), 1);
+    assert_eq!(containers[0].title, "Music");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].title, "Track");
+    assert_eq!(items[0].artist, Some("Artist".to_string()));
+    assert_eq!(items[0].url, "http://127.0.0.1/track.mp3");
+    assert_eq!(items[0].size, Some(1024));
+    assert_eq!(items[0].object_class, Some(ObjectClass::Audio));
+
+    let browse = device.action_requests("Browse");
+    assert_eq!(
+        browse[0].argument("BrowseFlag"),
+        Some("BrowseDirectChildren".to_string())
+    );
+}
@@ -0,0 +1,153 @@
+mod support;
+
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use support::{last_change, property_set, FakeDevice};
+use upnp_client::{
+    device_client::DeviceClient,
+    media_renderer::MediaRendererClient,
+    recording::{replay_notifications, Archive, Exchange, Recorder, ReplayTransport},
+    types::{AVTransportEvent, ClientConfig, Event, TransportState},
+};
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    let path = std::env::temp_dir().join(format!("upnp-client-{}.json", std::process::id()));
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "12")]);
+    let location = device.location();
+
+    let recorder = Recorder::new();
+    let device_client = DeviceClient::new(&location)
+        .unwrap()
+        .with_recorder(recorder.clone())
+        .connect()
+        .await
+        .unwrap();
+    let media_renderer = MediaRendererClient::new(device_client);
+    assert_eq!(media_renderer.get_volume().await.unwrap(), 12);
+    recorder.save(&path).unwrap();
+    drop(device);
+
+    let archive = Archive::load(&path).unwrap();
+    assert!(archive.exchanges.iter().any(|exchange| matches!(
+        exchange,
+        Exchange::Http { request, .. } if request.url == location
+    )));
+
+    let device_client = DeviceClient::new(&location)
+        .unwrap()
+        .with_transport(Arc::new(ReplayTransport::new(&archive)))
+        .connect()
+        .await
+        .unwrap();
+    let media_renderer = MediaRendererClient::new(device_client);
+    assert_eq!(media_renderer.get_volume().await.unwrap(), 12);
+    assert!(media_renderer.get_supported_protocols().await.is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_record_notify() {
+    let device = FakeDevice::start("media_renderer").await;
+    let recorder = Recorder::new();
+    let device_client = DeviceClient::new(&device.location())
+        .unwrap()
+        .with_recorder(recorder.clone())
+        .connect()
+        .await
+        .unwrap();
+    let _subscription = device_client.subscribe("ConnectionManager").await.unwrap();
+
+    let body = property_set(&[("CurrentConnectionIDs", "0")]);
+    assert_eq!(device.notify("ConnectionManager", 0, &body).await, 200);
+
+    let notifications = recorder.archive().notifications();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].1, body);
+    assert!(notifications[0]
+        .0
+        .iter()
+        .any(|(name, value)| name == "sid" && value == "uuid:fake-sid-1"));
+}
+
+#[tokio::test]
+async fn test_replay_notify() {
+    let device = FakeDevice::start("media_renderer").await;
+    let location = device.location();
+    let recorder = Recorder::new();
+    let device_client = DeviceClient::new(&location)
+        .unwrap()
+        .with_recorder(recorder.clone())
+        .connect()
+        .await
+        .unwrap();
+    let subscription = device_client.subscribe("AVTransport").await.unwrap();
+    for (seq, state) in ["STOPPED", "PLAYING"].iter().enumerate() {
+        let body = property_set(&[("LastChange", &last_change(&[("TransportState", state)]))]);
+        assert_eq!(device.notify("AVTransport", seq as u32, &body).await, 200);
+    }
+    let archive = recorder.archive();
+    drop(subscription);
+    drop(device);
+
+    let device_client = DeviceClient::new(&location)
+        .unwrap()
+        .with_transport(Arc::new(ReplayTransport::new(&archive)))
+        .connect()
+        .await
+        .unwrap();
+    let mut subscription = device_client.subscribe("AVTransport").await.unwrap();
+    assert_eq!(replay_notifications(&archive, &subscription), 2);
+
+    let mut states = vec![];
+    while states.len() < 2 {
+        match subscription.next().await.unwrap() {
+            Event::AVTransport(AVTransportEvent::TransportState {
+                transport_state, ..
+            }) => states.push(transport_state),
+            Event::StateVariables { .. } => {}
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+    assert_eq!(
+        states,
+        vec![TransportState::Stopped, TransportState::Playing]
+    );
+}
+
+#[tokio::test]
+async fn test_config_keeps_recorder_and_transport() {
+    let device = FakeDevice::start("media_renderer").await;
+    device.respond("GetVolume", &[("CurrentVolume", "7")]);
+    let location = device.location();
+
+    let recorder = Recorder::new();
+    let device_client = DeviceClient::new(&location)
+        .unwrap()
+        .with_recorder(recorder.clone())
+        .with_config(ClientConfig::default())
+        .connect()
+        .await
+        .unwrap();
+    let media_renderer = MediaRendererClient::new(device_client);
+    assert_eq!(media_renderer.get_volume().await.unwrap(), 7);
+    let archive = recorder.archive();
+    assert!(archive.exchanges.iter().any(|exchange| matches!(
+        exchange,
+        Exchange::Http { request, .. } if request.method == "POST"
+    )));
+    drop(device);
+
+    let device_client = DeviceClient::new(&location)
+        .unwrap()
+        .with_transport(Arc::new(ReplayTransport::new(&archive)))
+        .with_config(ClientConfig::default())
+        .connect()
+        .await
+        .unwrap();
+    let media_renderer = MediaRendererClient::new(device_client);
+    assert_eq!(media_renderer.get_volume().await.unwrap(), 7);
+}
@@ -0,0 +1,406 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    pub fn action(&self) -> Option<&str> {
+        self.header("soapaction")
+            .and_then(|soap_action| soap_action.trim_matches('"').split('#').nth(1))
+    }
+
+    /// Text of the first `<name>` element of the request body.
+    pub fn argument(&self, name: &str) -> Option<String> {
+        let start = format!("<{}>", name);
+        let end = format!("</{}>", name);
+        let from = self.body.find(&start)? + start.len();
+        let to = self.body[from..].find(&end)? + from;
+        Some(self.body[from..to].to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Scripted {
+    Arguments(Vec<(String, String)>),
+    Raw(u16, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub sid: String,
+    pub path: String,
+    pub callback: String,
+}
+
+#[derive(Default)]
+struct State {
+    fixtures: PathBuf,
+    responses: HashMap<String, Scripted>,
+    delays: HashMap<String, Duration>,
+    requests: Vec<RecordedRequest>,
+    subscriptions: Vec<Subscription>,
+    next_sid: u32,
+    subscription_timeout: Option<String>,
+}
+
+/// A UPnP device served on 127.0.0.1 from `tests/fixtures/<name>`.
+///
+/// Descriptions and SCPDs are served from the fixture directory, SOAP actions answer
+/// with scripted responses (an empty `<u:ActionResponse>` by default) and GENA
+/// subscriptions are accepted so that `notify` can push events to the subscriber.
+pub struct FakeDevice {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FakeDevice {
+    pub async fn start(fixture: &str) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            fixtures: PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(fixture),
+            ..Default::default()
+        }));
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+        });
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            rx.await.ok();
+        }));
+
+        Self {
+            addr,
+            state,
+            shutdown: Some(tx),
+        }
+    }
+
+    pub fn location(&self) -> String {
+        format!("http://{}/description.xml", self.addr)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Answer `action` with a SOAP response carrying the given output arguments.
+    pub fn respond(&self, action: &str, arguments: &[(&str, &str)]) {
+        let arguments = arguments
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(action.to_string(), Scripted::Arguments(arguments));
+    }
+
+    /// Answer `action` with the given status and body, as is.
+    pub fn respond_raw(&self, action: &str, status: u16, body: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(action.to_string(), Scripted::Raw(status, body.to_string()));
+    }
+
+    /// Stop scripting `action`, it is answered as usual again.
+    pub fn forget_response(&self, action: &str) {
+        self.state.lock().unwrap().responses.remove(action);
+    }
+
+    /// Grant subscriptions for `timeout`, `Second-1800` by default.
+    pub fn subscription_timeout(&self, timeout: &str) {
+        self.state.lock().unwrap().subscription_timeout = Some(timeout.to_string());
+    }
+
+    /// Hold the response to `action`, or to a GENA method such as `SUBSCRIBE`, back
+    /// for `delay`.
+    pub fn delay(&self, action: &str, delay: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .delays
+            .insert(action.to_string(), delay);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn action_requests(&self, action: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|req| req.action() == Some(action))
+            .collect()
+    }
+
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        self.state.lock().unwrap().subscriptions.clone()
+    }
+
+    /// Forget all subscriptions, as a device does when it reboots.
+    pub fn expire_subscriptions(&self) {
+        self.state.lock().unwrap().subscriptions.clear();
+    }
+
+    /// Send a GENA NOTIFY with `body` to the subscriber of `service`.
+    pub async fn notify(&self, service: &str, seq: u32, body: &str) -> u16 {
+        let subscription = self
+            .subscriptions()
+            .into_iter()
+            .rev()
+            .find(|s| s.path.starts_with(&format!("/{}/", service)))
+            .expect("no subscription for service");
+        self.notify_with_sid(&subscription, &subscription.sid, seq, body)
+            .await
+    }
+
+    pub async fn notify_with_sid(
+        &self,
+        subscription: &Subscription,
+        sid: &str,
+        seq: u32,
+        body: &str,
+    ) -> u16 {
+        let seq = seq.to_string();
+        let headers = [
+            ("CONTENT-TYPE", "text/xml; charset=\"utf-8\""),
+            ("NT", "upnp:event"),
+            ("NTS", "upnp:propchange"),
+            ("SID", sid),
+            ("SEQ", seq.as_str()),
+        ];
+        self.notify_raw(subscription, &headers, body).await
+    }
+
+    /// Send a NOTIFY with exactly the given headers, valid or not.
+    pub async fn notify_raw(
+        &self,
+        subscription: &Subscription,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> u16 {
+        let mut req = Request::builder()
+            .method("NOTIFY")
+            .uri(subscription.callback.as_str());
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        let res = hyper::Client::new().request(req).await.unwrap();
+        res.status().as_u16()
+    }
+}
+
+impl Drop for FakeDevice {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send(()).ok();
+        }
+    }
+}
+
+pub fn property_set(variables: &[(&str, &str)]) -> String {
+    let properties: String = variables
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "<e:property><{}>{}</{}></e:property>",
+                name,
+                escape(value),
+                name
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">{}</e:propertyset>"#,
+        properties
+    )
+}
+
+/// AVTransport or RenderingControl `LastChange` value for instance 0.
+pub fn last_change(variables: &[(&str, &str)]) -> String {
+    let variables: String = variables
+        .iter()
+        .map(|(name, value)| format!(r#"<{} val="{}"/>"#, name, escape(value)))
+        .collect();
+    format!(
+        r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/"><InstanceID val="0">{}</InstanceID></Event>"#,
+        variables
+    )
+}
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let headers = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_ascii_lowercase(),
+                value.to_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect();
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let request = RecordedRequest {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    };
+
+    let delay = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        let action = request.action().unwrap_or(request.method.as_str());
+        state.delays.get(action).cloned()
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    let mut state = state.lock().unwrap();
+
+    let response = match request.method.as_str() {
+        "GET" => match std::fs::read_to_string(state.fixtures.join(&request.path[1..])) {
+            Ok(xml) => Response::builder()
+                .header("CONTENT-TYPE", "text/xml; charset=\"utf-8\"")
+                .body(Body::from(xml)),
+            Err(_) => Response::builder().status(404).body(Body::empty()),
+        },
+        "POST" => {
+            let soap_action = request.header("soapaction").unwrap_or_default();
+            let (service_type, action) = soap_action
+                .trim_matches('"')
+                .split_once('#')
+                .unwrap_or_default();
+            let scripted = state
+                .responses
+                .get(action)
+                .cloned()
+                .unwrap_or(Scripted::Arguments(vec![]));
+            let (status, body) = match scripted {
+                Scripted::Arguments(arguments) => {
+                    (200, soap_response(service_type, action, &arguments))
+                }
+                Scripted::Raw(status, body) => (status, body),
+            };
+            Response::builder()
+                .status(status)
+                .header("CONTENT-TYPE", "text/xml; charset=\"utf-8\"")
+                .body(Body::from(body))
+        }
+        "SUBSCRIBE" if state.responses.contains_key("SUBSCRIBE") => {
+            let (status, body) = match state.responses["SUBSCRIBE"].clone() {
+                Scripted::Raw(status, body) => (status, body),
+                Scripted::Arguments(_) => (200, String::new()),
+            };
+            Response::builder().status(status).body(Body::from(body))
+        }
+        "SUBSCRIBE" => {
+            let sid = match request.header("sid") {
+                Some(sid) if !state.subscriptions.iter().any(|s| s.sid == sid) => {
+                    return Ok(Response::builder().status(412).body(Body::empty()).unwrap());
+                }
+                Some(sid) => sid.to_string(),
+                None => {
+                    state.next_sid += 1;
+                    let sid = format!("uuid:fake-sid-{}", state.next_sid);
+                    let callback = request
+                        .header("callback")
+                        .unwrap_or_default()
+                        .trim_matches(|c| c == '<' || c == '>')
+                        .to_string();
+                    state.subscriptions.push(Subscription {
+                        sid: sid.clone(),
+                        path: request.path.clone(),
+                        callback,
+                    });
+                    sid
+                }
+            };
+            let timeout = state
+                .subscription_timeout
+                .clone()
+                .unwrap_or_else(|| "Second-1800".to_string());
+            Response::builder()
+                .header("SID", sid)
+                .header("TIMEOUT", timeout)
+                .body(Body::empty())
+        }
+        "UNSUBSCRIBE" => {
+            let sid = request.header("sid").unwrap_or_default().to_string();
+            state.subscriptions.retain(|s| s.sid != sid);
+            Response::builder().body(Body::empty())
+        }
+        _ => Response::builder().status(405).body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+fn soap_response(service_type: &str, action: &str, arguments: &[(String, String)]) -> String {
+    let arguments: String = arguments
+        .iter()
+        .map(|(name, value)| format!("<{}>{}</{}>", name, escape(value), name))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{}Response xmlns:u="{}">{}</u:{}Response></s:Body></s:Envelope>"#,
+        action, service_type, arguments, action
+    )
+}
+
+pub fn upnp_error(code: u32, description: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"#,
+        code, description
+    )
+}